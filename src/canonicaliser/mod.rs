@@ -0,0 +1,326 @@
+use url::Url;
+
+/// Parameter names commonly used for click tracking and sessions which don't change page content.
+pub const TRACKING_PARAMS: [&str; 8] = [
+    "utm_*",
+    "gclid",
+    "fbclid",
+    "msclkid",
+    "sid",
+    "sessionid",
+    "jsessionid",
+    "phpsessid",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryParams {
+    Keep,
+    DropAll,
+    /// Only params whose names match one of the patterns are kept.
+    Allow(Vec<String>),
+    /// Params whose names match one of the patterns are removed.
+    Drop(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TrailingSlash {
+    Keep,
+    Strip,
+    Add,
+}
+
+/// Rewrites URLs into a canonical form so that equivalent URLs are only visited once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canonicaliser {
+    pub strip_fragment: bool,
+    pub query_params: QueryParams,
+    pub sort_query: bool,
+    pub trailing_slash: TrailingSlash,
+    pub normalise_percent_encoding: bool,
+}
+
+impl Default for Canonicaliser {
+    fn default() -> Self {
+        Canonicaliser {
+            strip_fragment: true,
+            query_params: QueryParams::Keep,
+            sort_query: false,
+            trailing_slash: TrailingSlash::Keep,
+            normalise_percent_encoding: true,
+        }
+    }
+}
+
+// patterns match case-insensitively and a trailing `*` matches any suffix e.g. `utm_*`
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Decodes percent-encoded unreserved characters and uppercases the hex digits of
+/// any escapes that remain (RFC 3986 section 6.2.2). Encoded dots are kept in paths, where
+/// decoding them could turn a segment into `..`.
+fn normalise_percent_encoding(value: &str, keep_dots: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut out = String::with_capacity(value.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                let decoded = hi * 16 + lo;
+                if is_unreserved(decoded) && !(keep_dots && decoded == b'.') {
+                    out.push(decoded as char);
+                } else {
+                    out.push('%');
+                    out.push(bytes[i + 1].to_ascii_uppercase() as char);
+                    out.push(bytes[i + 2].to_ascii_uppercase() as char);
+                }
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i] as char);
+        i += 1;
+    }
+    out
+}
+
+impl Canonicaliser {
+    pub fn canonicalise(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+
+        if self.strip_fragment {
+            url.set_fragment(None);
+        }
+
+        let path = url.path().to_string();
+        let path = match self.trailing_slash {
+            TrailingSlash::Keep => path,
+            TrailingSlash::Strip => match path.strip_suffix('/') {
+                Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+                _ => path,
+            },
+            TrailingSlash::Add => {
+                let last_segment = path.rsplit('/').next().unwrap_or("");
+                if path.ends_with('/') || last_segment.contains('.') {
+                    path
+                } else {
+                    path + "/"
+                }
+            }
+        };
+        let path = match self.normalise_percent_encoding {
+            true => normalise_percent_encoding(&path, true),
+            false => path,
+        };
+        url.set_path(&path);
+
+        if let Some(query) = url.query().map(|q| q.to_string()) {
+            let mut params: Vec<&str> = query
+                .split('&')
+                .filter(|param| !param.is_empty())
+                .filter(|param| {
+                    let name = param.split('=').next().unwrap_or("");
+                    match &self.query_params {
+                        QueryParams::Keep => true,
+                        QueryParams::DropAll => false,
+                        QueryParams::Allow(patterns) => {
+                            patterns.iter().any(|p| matches_pattern(name, p))
+                        }
+                        QueryParams::Drop(patterns) => {
+                            !patterns.iter().any(|p| matches_pattern(name, p))
+                        }
+                    }
+                })
+                .collect();
+            if self.sort_query {
+                params.sort_by_key(|param| param.split('=').next().unwrap_or(""));
+            }
+            let query = params.join("&");
+            let query = match self.normalise_percent_encoding {
+                true => normalise_percent_encoding(&query, false),
+                false => query,
+            };
+            url.set_query(match query.is_empty() {
+                true => None,
+                false => Some(&query),
+            });
+        }
+
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn canonicalise(canonicaliser: Canonicaliser, url: &str) -> String {
+        canonicaliser.canonicalise(&to_url(url)).to_string()
+    }
+
+    #[test]
+    fn default_strips_fragment() {
+        assert_eq!(
+            canonicalise(Canonicaliser::default(), "http://example.com/one#two"),
+            "http://example.com/one"
+        );
+        assert_eq!(
+            canonicalise(
+                Canonicaliser {
+                    strip_fragment: false,
+                    ..Canonicaliser::default()
+                },
+                "http://example.com/one#two"
+            ),
+            "http://example.com/one#two"
+        );
+    }
+
+    #[test]
+    fn default_keeps_query_params() {
+        assert_eq!(
+            canonicalise(
+                Canonicaliser::default(),
+                "http://example.com/?b=2&utm_source=x&a=1"
+            ),
+            "http://example.com/?b=2&utm_source=x&a=1"
+        );
+    }
+
+    #[test]
+    fn drops_all_query_params() {
+        let canonicaliser = Canonicaliser {
+            query_params: QueryParams::DropAll,
+            ..Canonicaliser::default()
+        };
+        assert_eq!(
+            canonicalise(canonicaliser, "http://example.com/one?b=2&a=1"),
+            "http://example.com/one"
+        );
+    }
+
+    #[test]
+    fn drops_matching_query_params() {
+        let canonicaliser = Canonicaliser {
+            query_params: QueryParams::Drop(TRACKING_PARAMS.map(String::from).to_vec()),
+            ..Canonicaliser::default()
+        };
+        assert_eq!(
+            canonicalise(
+                canonicaliser.clone(),
+                "http://example.com/one?utm_source=news&page=2&UTM_Medium=email&JSESSIONID=abc"
+            ),
+            "http://example.com/one?page=2"
+        );
+        assert_eq!(
+            canonicalise(canonicaliser, "http://example.com/one?utm_source=news"),
+            "http://example.com/one"
+        );
+    }
+
+    #[test]
+    fn allows_listed_query_params() {
+        let canonicaliser = Canonicaliser {
+            query_params: QueryParams::Allow(vec!["page".to_string(), "filter_*".to_string()]),
+            ..Canonicaliser::default()
+        };
+        assert_eq!(
+            canonicalise(
+                canonicaliser,
+                "http://example.com/?sid=1&page=2&filter_colour=red&ref=abc"
+            ),
+            "http://example.com/?page=2&filter_colour=red"
+        );
+    }
+
+    #[test]
+    fn sorts_query_keys() {
+        let canonicaliser = Canonicaliser {
+            sort_query: true,
+            ..Canonicaliser::default()
+        };
+        assert_eq!(
+            canonicalise(canonicaliser, "http://example.com/?c=3&a=1&b=2&a=0"),
+            "http://example.com/?a=1&a=0&b=2&c=3"
+        );
+    }
+
+    #[test]
+    fn strips_trailing_slash() {
+        let canonicaliser = Canonicaliser {
+            trailing_slash: TrailingSlash::Strip,
+            ..Canonicaliser::default()
+        };
+        assert_eq!(
+            canonicalise(canonicaliser.clone(), "http://example.com/one/?a=1"),
+            "http://example.com/one?a=1"
+        );
+        assert_eq!(
+            canonicalise(canonicaliser, "http://example.com/"),
+            "http://example.com/"
+        );
+    }
+
+    #[test]
+    fn adds_trailing_slash() {
+        let canonicaliser = Canonicaliser {
+            trailing_slash: TrailingSlash::Add,
+            ..Canonicaliser::default()
+        };
+        assert_eq!(
+            canonicalise(canonicaliser.clone(), "http://example.com/one"),
+            "http://example.com/one/"
+        );
+        assert_eq!(
+            canonicalise(canonicaliser, "http://example.com/one/file.pdf"),
+            "http://example.com/one/file.pdf"
+        );
+    }
+
+    #[test]
+    fn normalises_percent_encoding() {
+        assert_eq!(
+            canonicalise(
+                Canonicaliser::default(),
+                "http://example.com/%7euser/a%2fb%3f?q=%61%2b%2B"
+            ),
+            "http://example.com/~user/a%2Fb%3F?q=a%2B%2B"
+        );
+        assert_eq!(
+            canonicalise(
+                Canonicaliser {
+                    normalise_percent_encoding: false,
+                    ..Canonicaliser::default()
+                },
+                "http://example.com/%7euser"
+            ),
+            "http://example.com/%7euser"
+        );
+    }
+
+    #[test]
+    fn keeps_encoded_dots_in_path() {
+        assert_eq!(
+            canonicalise(
+                Canonicaliser::default(),
+                "http://example.com/one/%2e%2e%2e/v%2e1?q=%2e"
+            ),
+            "http://example.com/one/%2E%2E%2E/v%2E1?q=."
+        );
+    }
+}
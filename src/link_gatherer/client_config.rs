@@ -0,0 +1,683 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use reqwest::{
+    cookie::Jar,
+    dns::{Addrs, Name, Resolving},
+    redirect, Certificate, Identity, NoProxy, Proxy,
+};
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+/// The user agent sent unless configured otherwise.
+pub const USER_AGENT: &str = "scrapey/1.0";
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ClientConfigError {
+    #[error("unable to read client config")]
+    Io(String),
+    #[error("invalid client config")]
+    Format(String),
+    #[error("unable to create HTTP client")]
+    Client(String),
+}
+
+/// What to do with a redirect response.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectPolicy {
+    /// Return it so that the tracer records each hop
+    Record,
+    /// Follow it while fetching the page, still recording each hop
+    Follow,
+}
+
+/// A content encoding decompressed by the client, which is then requested with Accept-Encoding.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Brotli,
+    Deflate,
+}
+
+/// Connects to an address for a host rather than looking it up, given as `host:port:addr`
+/// e.g. `www.example.com:443:10.0.0.5`. As with curl, the address is only used for URLs on that
+/// port, which is the scheme's default when the URL has none.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Resolve {
+    pub host: String,
+    pub addr: SocketAddr,
+}
+
+impl FromStr for Resolve {
+    type Err = String;
+
+    fn from_str(resolve: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected `host:port:addr` but got {}", resolve);
+        let mut parts = resolve.splitn(3, ':');
+        let (Some(host), Some(port), Some(addr)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let port = port.parse().map_err(|_| invalid())?;
+        let addr: IpAddr = addr
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| invalid())?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Resolve {
+            host: host.to_string(),
+            addr: SocketAddr::new(addr, port),
+        })
+    }
+}
+
+impl TryFrom<String> for Resolve {
+    type Error = String;
+
+    fn try_from(resolve: String) -> Result<Self, Self::Error> {
+        resolve.parse()
+    }
+}
+
+tokio::task_local! {
+    // the port a request is sent to, which reqwest doesn't pass on to its resolver
+    static PORT: u16;
+}
+
+/// Sends a request to the URL, letting the client's resolver match `Resolve`s on its port.
+pub async fn with_port<F: Future>(url: &Url, send: F) -> F::Output {
+    PORT.scope(url.port_or_known_default().unwrap_or(0), send)
+        .await
+}
+
+/// Resolves a host to the addresses given for it on the port being requested, and looks it up
+/// otherwise.
+struct ResolveOverrides(HashMap<(String, u16), Vec<SocketAddr>>);
+
+impl reqwest::dns::Resolve for ResolveOverrides {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        let addrs = PORT
+            .try_with(|port| self.0.get(&(host.clone(), *port)).cloned())
+            .ok()
+            .flatten();
+        Box::pin(async move {
+            let addrs: Addrs = match addrs {
+                Some(addrs) => Box::new(addrs.into_iter()),
+                None => Box::new(
+                    tokio::task::spawn_blocking(move || (host.as_str(), 0).to_socket_addrs())
+                        .await??,
+                ),
+            };
+            Ok(addrs)
+        })
+    }
+}
+
+/// Settings of the HTTP client, read from a JSON file with any of these fields.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub user_agent: String,
+    /// Seconds to wait to connect to a server
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds to wait for a whole response, including its body
+    pub timeout_secs: Option<u64>,
+    pub redirects: RedirectPolicy,
+    /// Most redirects followed for one request when following them
+    pub max_redirects: usize,
+    pub decompress: Vec<Compression>,
+    /// Use HTTP/2 without negotiating it, for servers known to support it
+    pub http2_prior_knowledge: bool,
+    /// Most idle connections kept open to each host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Send every request through this proxy e.g. `http://proxy:3128` or `socks5://proxy:1080`
+    pub proxy: Option<String>,
+    /// Hosts, domains (with a leading `.`), IPs or networks reached without the proxy
+    pub no_proxy: Vec<String>,
+    /// PEM files of CA certificates to trust in addition to the system's
+    pub ca_certs: Vec<PathBuf>,
+    /// PEM file of a certificate to identify the client with mutual TLS, along with `client_key`
+    pub client_cert: Option<PathBuf>,
+    /// PEM file of the client certificate's PKCS #8 private key
+    pub client_key: Option<PathBuf>,
+    /// Accept invalid certificates, including those of unknown CAs or for other hosts
+    pub insecure: bool,
+    /// Addresses to connect to for hosts in place of looking them up
+    pub resolve: Vec<Resolve>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            user_agent: USER_AGENT.to_string(),
+            connect_timeout_secs: Some(10),
+            timeout_secs: Some(30),
+            redirects: RedirectPolicy::Record,
+            max_redirects: 10,
+            decompress: vec![Compression::Gzip, Compression::Brotli, Compression::Deflate],
+            http2_prior_knowledge: false,
+            pool_max_idle_per_host: None,
+            proxy: None,
+            no_proxy: vec![],
+            ca_certs: vec![],
+            client_cert: None,
+            client_key: None,
+            insecure: false,
+            resolve: vec![],
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn load(path: &Path) -> Result<Self, ClientConfigError> {
+        let content = fs::read(path).map_err(|err| ClientConfigError::Io(err.to_string()))?;
+        serde_json::from_slice(&content).map_err(|err| ClientConfigError::Format(err.to_string()))
+    }
+
+    fn proxy(&self) -> Result<Option<Proxy>, ClientConfigError> {
+        let Some(proxy) = &self.proxy else {
+            return Ok(None);
+        };
+        let proxy = Proxy::all(proxy).map_err(|err| ClientConfigError::Format(err.to_string()))?;
+        Ok(Some(
+            proxy.no_proxy(NoProxy::from_string(&self.no_proxy.join(","))),
+        ))
+    }
+
+    fn identity(&self) -> Result<Option<Identity>, ClientConfigError> {
+        let (cert, key) = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => (read(cert)?, read(key)?),
+            (None, None) => return Ok(None),
+            _ => {
+                return Err(ClientConfigError::Format(
+                    "client_cert and client_key must be given together".to_string(),
+                ))
+            }
+        };
+        Identity::from_pkcs8_pem(&cert, &key)
+            .map(Some)
+            .map_err(|err| ClientConfigError::Format(err.to_string()))
+    }
+
+    /// Most redirects the `HttpClient` follows itself, so that each hop is authorised for its
+    /// own host, or `None` when they're recorded.
+    pub fn follow_redirects(&self) -> Option<usize> {
+        match self.redirects {
+            RedirectPolicy::Record => None,
+            RedirectPolicy::Follow => Some(self.max_redirects),
+        }
+    }
+
+    /// A client keeping cookies in the jar, which never follows redirects itself.
+    pub fn build(&self, cookies: Arc<Jar>) -> Result<reqwest::Client, ClientConfigError> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .redirect(redirect::Policy::none())
+            .gzip(self.decompress.contains(&Compression::Gzip))
+            .brotli(self.decompress.contains(&Compression::Brotli))
+            .deflate(self.decompress.contains(&Compression::Deflate))
+            .cookie_provider(cookies);
+        if let Some(secs) = self.connect_timeout_secs {
+            builder = builder.connect_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.timeout_secs {
+            builder = builder.timeout(Duration::from_secs(secs));
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(proxy) = self.proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        for path in &self.ca_certs {
+            let certs = Certificate::from_pem_bundle(&read(path)?)
+                .map_err(|err| ClientConfigError::Format(err.to_string()))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(identity) = self.identity()? {
+            builder = builder.identity(identity);
+        }
+        if !self.resolve.is_empty() {
+            let mut overrides: HashMap<_, Vec<_>> = HashMap::new();
+            for resolve in &self.resolve {
+                overrides
+                    .entry((resolve.host.to_ascii_lowercase(), resolve.addr.port()))
+                    .or_default()
+                    .push(resolve.addr);
+            }
+            builder = builder.dns_resolver(Arc::new(ResolveOverrides(overrides)));
+        }
+        if self.insecure {
+            tracing::warn!("Accepting invalid certificates");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        builder
+            .build()
+            .map_err(|err| ClientConfigError::Client(err.to_string()))
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, ClientConfigError> {
+    fs::read(path).map_err(|err| ClientConfigError::Io(format!("{} - {}", path.display(), err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+    use url::Url;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::link_gatherer::{
+        url_content_getter::{BodyPolicy, Conditions},
+        Credentials, HttpClient, URLContentGetter, URLContentGetterError,
+    };
+
+    use super::*;
+
+    fn client(config: &ClientConfig) -> HttpClient {
+        HttpClient {
+            follow_redirects: config.follow_redirects(),
+            ..HttpClient::from(config.build(Arc::new(Jar::default())).unwrap())
+        }
+    }
+
+    #[test]
+    fn loads_config_file() {
+        let path = std::env::temp_dir().join(format!("client-config-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{ "user_agent": "otherbot/2.0", "redirects": "follow", "decompress": ["gzip"] }"#,
+        )
+        .unwrap();
+        let config = ClientConfig::load(&path);
+        fs::write(&path, r#"{ "timeout": 5 }"#).unwrap();
+        let unknown = ClientConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            config,
+            Ok(ClientConfig {
+                user_agent: "otherbot/2.0".to_string(),
+                redirects: RedirectPolicy::Follow,
+                decompress: vec![Compression::Gzip],
+                ..ClientConfig::default()
+            })
+        );
+        assert!(matches!(unknown, Err(ClientConfigError::Format(_))));
+    }
+
+    #[tokio::test]
+    async fn builds_client_from_config() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/old"))
+            .respond_with(ResponseTemplate::new(301).insert_header("location", "/new"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/new"))
+            .and(header("user-agent", "otherbot/2.0"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("new"))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/old", server.uri())).unwrap();
+        let config = ClientConfig {
+            user_agent: "otherbot/2.0".to_string(),
+            ..ClientConfig::default()
+        };
+
+        let get = |config: ClientConfig| {
+            let url = url.clone();
+            async move {
+                client(&config)
+                    .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+                    .await
+            }
+        };
+
+        let response = get(config.clone()).await.unwrap();
+        assert_eq!(response.redirect.map(|redirect| redirect.status), Some(301));
+        let config = ClientConfig {
+            redirects: RedirectPolicy::Follow,
+            ..config
+        };
+        let response = get(config.clone()).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "new");
+        let config = ClientConfig {
+            max_redirects: 0,
+            ..config
+        };
+        assert!(matches!(
+            get(config).await,
+            Err(URLContentGetterError::Request(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn follows_redirects_to_other_hosts_without_their_credentials() {
+        let server = MockServer::start().await;
+        let other = MockServer::start().await;
+        // the other server under another name stands in for an external host
+        let mut moved = Url::parse(&format!("{}/new", other.uri())).unwrap();
+        moved.set_host(Some("localhost")).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/old"))
+            .and(header("x-api-key", "secret"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", moved.as_str()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/new"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("new"))
+            .expect(1)
+            .mount(&other)
+            .await;
+        let url = Url::parse(&format!("{}/old", server.uri())).unwrap();
+        let client = HttpClient {
+            credentials: Credentials {
+                hosts: vec![url.host_str().unwrap().to_string()],
+                headers: [("x-api-key".parse().unwrap(), "secret".parse().unwrap())]
+                    .into_iter()
+                    .collect(),
+                ..Credentials::default()
+            },
+            ..client(&ClientConfig {
+                redirects: RedirectPolicy::Follow,
+                ..ClientConfig::default()
+            })
+        };
+
+        let response = client
+            .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.body, "new");
+        assert_eq!(response.final_url, Some(moved));
+        let requests = other.received_requests().await.unwrap();
+        assert!(requests
+            .iter()
+            .all(|request| !request.headers.contains_key("x-api-key")));
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_responses() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+            .mount(&server)
+            .await;
+        let config = ClientConfig {
+            timeout_secs: Some(1),
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(
+            client(&config)
+                .get_http_response_body(&Url::parse(&server.uri()).unwrap())
+                .await,
+            Err(URLContentGetterError::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_requests_through_proxy() {
+        let proxy = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("proxied"))
+            .mount(&proxy)
+            .await;
+        let url = Url::parse("http://crawl.invalid/page").unwrap();
+        let config = ClientConfig {
+            proxy: Some(proxy.uri()),
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(
+            client(&config).get_http_response_body(&url).await,
+            Ok("proxied".to_string())
+        );
+        let config = ClientConfig {
+            no_proxy: vec!["other.invalid".to_string(), ".invalid".to_string()],
+            ..config
+        };
+        assert_eq!(
+            client(&config).get_http_response_body(&url).await,
+            Err(URLContentGetterError::Request(0))
+        );
+        let config = ClientConfig {
+            proxy: Some("socks5://127.0.0.1:1080".to_string()),
+            ..ClientConfig::default()
+        };
+        assert!(config.build(Arc::default()).is_ok());
+    }
+
+    #[test]
+    fn parses_resolve() {
+        assert_eq!(
+            "www.example.com:443:10.0.0.5".parse(),
+            Ok(Resolve {
+                host: "www.example.com".to_string(),
+                addr: "10.0.0.5:443".parse().unwrap(),
+            })
+        );
+        assert_eq!(
+            "example.com:80:[::1]"
+                .parse::<Resolve>()
+                .map(|resolve| resolve.addr),
+            Ok("[::1]:80".parse().unwrap())
+        );
+        assert!("example.com:10.0.0.5".parse::<Resolve>().is_err());
+        assert!("example.com:http:10.0.0.5".parse::<Resolve>().is_err());
+    }
+
+    #[tokio::test]
+    async fn connects_to_resolved_address() {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        Mock::given(method("GET"))
+            .and(header("host", format!("www.example.invalid:{}", port)))
+            .respond_with(ResponseTemplate::new(200).set_body_string("resolved"))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("http://www.example.invalid:{}/", port)).unwrap();
+        let config = ClientConfig {
+            resolve: vec![format!("www.example.invalid:{}:127.0.0.1", port)
+                .parse()
+                .unwrap()],
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(
+            client(&ClientConfig::default())
+                .get_http_response_body(&url)
+                .await,
+            Err(URLContentGetterError::Request(0))
+        );
+        assert_eq!(
+            client(&config).get_http_response_body(&url).await,
+            Ok("resolved".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_host_to_address_given_for_its_port() {
+        let first = MockServer::start().await;
+        let second = MockServer::builder()
+            .listener(TcpListener::bind("127.0.0.2:0").unwrap())
+            .start()
+            .await;
+        for (server, body) in [(&first, "first"), (&second, "second")] {
+            Mock::given(method("GET"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(server)
+                .await;
+        }
+        let (first_port, second_port) = (first.address().port(), second.address().port());
+        let url = |port| Url::parse(&format!("http://www.example.invalid:{}/", port)).unwrap();
+        let config = ClientConfig {
+            resolve: vec![
+                format!("www.example.invalid:{}:127.0.0.1", first_port)
+                    .parse()
+                    .unwrap(),
+                format!("www.example.invalid:{}:127.0.0.2", second_port)
+                    .parse()
+                    .unwrap(),
+            ],
+            ..ClientConfig::default()
+        };
+        let first_only = ClientConfig {
+            resolve: config.resolve[..1].to_vec(),
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(
+            client(&config)
+                .get_http_response_body(&url(first_port))
+                .await,
+            Ok("first".to_string())
+        );
+        assert_eq!(
+            client(&config)
+                .get_http_response_body(&url(second_port))
+                .await,
+            Ok("second".to_string())
+        );
+        assert_eq!(
+            client(&first_only)
+                .get_http_response_body(&url(second_port))
+                .await,
+            Err(URLContentGetterError::Request(0))
+        );
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    // an HTTPS server for localhost with a certificate from the fixtures' CA, which requires a
+    // client certificate from that CA when `mutual` is set
+    fn tls_server(mutual: bool) -> Url {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor
+            .set_certificate_chain_file(fixture("server.pem"))
+            .unwrap();
+        acceptor
+            .set_private_key_file(fixture("server.key"), SslFiletype::PEM)
+            .unwrap();
+        if mutual {
+            acceptor.set_ca_file(fixture("ca.pem")).unwrap();
+            acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(mut stream) = acceptor.accept(stream) else {
+                    continue;
+                };
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 6\r\nconnection: close\r\n\r\nsecure",
+                );
+            }
+        });
+        Url::parse(&format!("https://localhost:{}/", port)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn trusts_given_ca_certs_or_any_when_insecure() {
+        let url = tls_server(false);
+        let get = |config: ClientConfig| {
+            let url = url.clone();
+            async move { client(&config).get_http_response_body(&url).await }
+        };
+
+        assert_eq!(
+            get(ClientConfig::default()).await,
+            Err(URLContentGetterError::Request(0))
+        );
+        let config = ClientConfig {
+            ca_certs: vec![fixture("ca.pem")],
+            ..ClientConfig::default()
+        };
+        assert_eq!(get(config).await, Ok("secure".to_string()));
+        let config = ClientConfig {
+            insecure: true,
+            ..ClientConfig::default()
+        };
+        assert_eq!(get(config).await, Ok("secure".to_string()));
+    }
+
+    #[tokio::test]
+    async fn identifies_with_client_cert() {
+        let url = tls_server(true);
+        let config = ClientConfig {
+            ca_certs: vec![fixture("ca.pem")],
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(
+            client(&config).get_http_response_body(&url).await,
+            Err(URLContentGetterError::Request(0))
+        );
+        let config = ClientConfig {
+            client_cert: Some(fixture("client.pem")),
+            client_key: Some(fixture("client.key")),
+            ..config
+        };
+        assert_eq!(
+            client(&config).get_http_response_body(&url).await,
+            Ok("secure".to_string())
+        );
+        let config = ClientConfig {
+            client_key: None,
+            ..config
+        };
+        assert!(matches!(
+            config.build(Arc::default()),
+            Err(ClientConfigError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+use jiff::Timestamp;
+use reqwest::{
+    cookie::Jar,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    RequestBuilder,
+};
+use thiserror::Error;
+use url::Url;
+
+use super::HostRewrite;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CredentialsError {
+    #[error("invalid header")]
+    Header(String),
+    #[error("invalid cookies file")]
+    Cookies(String),
+}
+
+/// Headers, authorization and cookies sent with requests to the crawl's hosts, and never to
+/// any other host.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    /// Hosts sent the credentials
+    pub hosts: Vec<String>,
+    /// Also send the credentials to subdomains of the hosts
+    pub include_subdomains: bool,
+    pub headers: HeaderMap,
+    /// Username and optional password for HTTP basic auth
+    pub basic_auth: Option<(String, Option<String>)>,
+    pub bearer_token: Option<String>,
+}
+
+/// A header given as `Name: value`.
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), CredentialsError> {
+    let invalid = || CredentialsError::Header(header.to_string());
+    let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+    Ok((
+        name.trim().parse().map_err(|_| invalid())?,
+        value.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+impl Credentials {
+    pub fn applies_to(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('.');
+        self.hosts.iter().any(|allowed| {
+            allowed.eq_ignore_ascii_case(host)
+                || (self.include_subdomains
+                    && host
+                        .to_ascii_lowercase()
+                        .ends_with(&format!(".{}", allowed.to_ascii_lowercase())))
+        })
+    }
+
+    /// Adds the credentials to a request for `url` if it's on one of the hosts.
+    pub(super) fn authorise(&self, url: &Url, request: RequestBuilder) -> RequestBuilder {
+        if !url.host_str().is_some_and(|host| self.applies_to(host)) {
+            return request;
+        }
+        let request = request.headers(self.headers.clone());
+        let request = match &self.basic_auth {
+            Some((username, password)) => request.basic_auth(username, password.as_ref()),
+            None => request,
+        };
+        match &self.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Adds the cookies for the hosts from a Netscape cookies.txt file to the jar, skipping those
+    /// which have expired. Cookies for a host which is rewritten are added for the origin it's
+    /// fetched from too, as that is where requests for it are sent. Returns the number of
+    /// cookies added.
+    pub fn load_cookies(
+        &self,
+        jar: &Jar,
+        cookies_txt: &str,
+        rewrites: &[HostRewrite],
+    ) -> Result<usize, CredentialsError> {
+        let now = Timestamp::now().as_second();
+        let mut added = 0;
+        for (number, line) in cookies_txt.lines().enumerate() {
+            // HttpOnly cookies are commented out with a prefix to hide them from older parsers
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || CredentialsError::Cookies(format!("line {}", number + 1));
+            let [domain, include_subdomains, path, secure, expires, name, value] =
+                <[&str; 7]>::try_from(line.split('\t').collect::<Vec<_>>())
+                    .map_err(|_| invalid())?;
+            let expires: i64 = expires.parse().map_err(|_| invalid())?;
+            if (expires != 0 && expires < now) || !self.applies_to(domain) {
+                continue;
+            }
+            let host = domain.trim_start_matches('.');
+            let include_subdomains = include_subdomains.eq_ignore_ascii_case("TRUE");
+            let secure = secure.eq_ignore_ascii_case("TRUE");
+            let url = Url::parse(&format!(
+                "{}://{}{}",
+                if secure { "https" } else { "http" },
+                host,
+                path
+            ))
+            .map_err(|_| invalid())?;
+            let mut cookie = format!("{}={}; Path={}", name, value, path);
+            // without a Domain the cookie is only sent to the exact host
+            if include_subdomains {
+                cookie.push_str(&format!("; Domain={}", host));
+            }
+            if secure {
+                cookie.push_str("; Secure");
+            }
+            jar.add_cookie_str(&cookie, &url);
+
+            let rewritten = rewrites.iter().filter(|rewrite| {
+                rewrite.from.host_str().is_some_and(|from| {
+                    from.eq_ignore_ascii_case(host)
+                        || (include_subdomains
+                            && from.to_ascii_lowercase().ends_with(&format!(".{}", host)))
+                })
+            });
+            for rewrite in rewritten {
+                // only sent to the host fetched from, which may not serve https
+                let url = rewrite.to.join(path).map_err(|_| invalid())?;
+                let mut cookie = format!("{}={}; Path={}", name, value, path);
+                if secure && url.scheme() == "https" {
+                    cookie.push_str("; Secure");
+                }
+                jar.add_cookie_str(&cookie, &url);
+            }
+            added += 1;
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::cookie::CookieStore;
+
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    // sorted as the jar's order isn't defined
+    fn cookies(jar: &Jar, url: &str) -> Option<String> {
+        jar.cookies(&to_url(url)).map(|value| {
+            let mut cookies: Vec<_> = value.to_str().unwrap().split("; ").collect();
+            cookies.sort();
+            cookies.join("; ")
+        })
+    }
+
+    #[test]
+    fn parses_headers() {
+        assert_eq!(
+            parse_header("X-Api-Key:  secret "),
+            Ok((
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_static("secret")
+            ))
+        );
+        assert_eq!(
+            parse_header("no colon"),
+            Err(CredentialsError::Header("no colon".to_string()))
+        );
+    }
+
+    #[test]
+    fn applies_to_the_crawls_hosts() {
+        let credentials = Credentials {
+            hosts: vec!["example.com".to_string()],
+            ..Credentials::default()
+        };
+        assert!(credentials.applies_to("EXAMPLE.com"));
+        assert!(!credentials.applies_to("www.example.com"));
+        assert!(!credentials.applies_to("example.com.evil.net"));
+        let credentials = Credentials {
+            include_subdomains: true,
+            ..credentials
+        };
+        assert!(credentials.applies_to("www.example.com"));
+        assert!(credentials.applies_to(".example.com"));
+        assert!(!credentials.applies_to("evilexample.com"));
+    }
+
+    #[test]
+    fn loads_cookies_for_the_crawls_hosts() {
+        let credentials = Credentials {
+            hosts: vec!["example.com".to_string(), "www.example.com".to_string()],
+            ..Credentials::default()
+        };
+        let cookies_txt = "# Netscape HTTP Cookie File\n\
+            \n\
+            .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n\
+            #HttpOnly_example.com\tFALSE\t/docs\tTRUE\t0\ttoken\txyz\n\
+            example.com\tFALSE\t/\tFALSE\t1\texpired\told\n\
+            other.com\tFALSE\t/\tFALSE\t0\ttracker\t123\n";
+        let jar = Jar::default();
+
+        assert_eq!(credentials.load_cookies(&jar, cookies_txt, &[]), Ok(2));
+        assert_eq!(
+            cookies(&jar, "https://example.com/docs/one"),
+            Some("session=abc; token=xyz".to_string())
+        );
+        assert_eq!(
+            cookies(&jar, "http://www.example.com/docs/one"),
+            Some("session=abc".to_string())
+        );
+        assert_eq!(cookies(&jar, "http://other.com/"), None);
+        assert_eq!(
+            credentials.load_cookies(&jar, "example.com\tFALSE\t/\n", &[]),
+            Err(CredentialsError::Cookies("line 1".to_string()))
+        );
+    }
+
+    #[test]
+    fn loads_cookies_for_rewritten_hosts_under_the_origin_fetched_from() {
+        let credentials = Credentials {
+            hosts: vec!["example.com".to_string()],
+            include_subdomains: true,
+            ..Credentials::default()
+        };
+        let cookies_txt = ".example.com\tTRUE\t/\tTRUE\t0\tsession\tabc\n\
+            www.example.com\tFALSE\t/docs\tFALSE\t0\ttoken\txyz\n";
+        let rewrites = [
+            "https://www.example.com=http://localhost:8080"
+                .parse()
+                .unwrap(),
+            "https://other.com=http://127.0.0.1:9090".parse().unwrap(),
+        ];
+        let jar = Jar::default();
+
+        assert_eq!(
+            credentials.load_cookies(&jar, cookies_txt, &rewrites),
+            Ok(2)
+        );
+        assert_eq!(
+            cookies(&jar, "http://localhost:8080/docs/one"),
+            Some("session=abc; token=xyz".to_string())
+        );
+        assert_eq!(
+            cookies(&jar, "https://www.example.com/docs/one"),
+            Some("session=abc; token=xyz".to_string())
+        );
+        assert_eq!(cookies(&jar, "http://127.0.0.1:9090/"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use url::Url;
+
+use super::{super::links::css_urls, to_link, Link, LinkExtractor, LinkKind};
+
+// the URLs of `@import "..."` rules, those given with `url()` are found with the rest
+fn import_urls(css: &str) -> Vec<&str> {
+    css.match_indices("@import")
+        .filter_map(|(start, _)| {
+            let rest = css[start + 7..].trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            rest[1..].split(quote).next()
+        })
+        .collect()
+}
+
+/// The `url()`s and `@import`s of a stylesheet, as styles.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CssExtractor;
+
+impl LinkExtractor for CssExtractor {
+    fn content_types(&self) -> &[&str] {
+        &["text/css"]
+    }
+
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link> {
+        import_urls(body)
+            .into_iter()
+            .chain(css_urls(body))
+            .filter_map(|link| to_link(link, LinkKind::Style, base))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    #[test]
+    fn extracts_imports_and_urls() {
+        let css = r#"@import "reset.css";
+@import 'theme.css' screen;
+@import url("print.css") print;
+body { background: url(/img/bg.png) }
+@font-face { src: url('../fonts/a.woff2') format("woff2") }"#;
+        assert_eq!(
+            CssExtractor
+                .extract_links(css, &to_url("http://example.com/css/main.css"))
+                .into_iter()
+                .map(|link| link.url)
+                .collect::<Vec<_>>(),
+            vec![
+                to_url("http://example.com/css/reset.css"),
+                to_url("http://example.com/css/theme.css"),
+                to_url("http://example.com/css/print.css"),
+                to_url("http://example.com/img/bg.png"),
+                to_url("http://example.com/fonts/a.woff2"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use url::Url;
+
+use super::{to_link, Link, LinkExtractor, LinkKind};
+
+/// The `<link>`s of RSS and Atom feeds, as anchors, and their `<enclosure>`s, as sources.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FeedExtractor;
+
+impl LinkExtractor for FeedExtractor {
+    fn content_types(&self) -> &[&str] {
+        &["application/rss+xml", "application/atom+xml"]
+    }
+
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link> {
+        let doc = match roxmltree::Document::parse(body) {
+            Ok(doc) => doc,
+            Err(err) => {
+                tracing::debug!("Unable to parse feed - {}", err);
+                return vec![];
+            }
+        };
+        doc.descendants()
+            .filter(|node| node.is_element())
+            .filter_map(|node| match node.tag_name().name() {
+                // RSS gives the URL as text and Atom as an attribute
+                "link" => to_link(
+                    node.attribute("href").or(node.text())?,
+                    LinkKind::Anchor,
+                    base,
+                ),
+                "enclosure" => to_link(node.attribute("url")?, LinkKind::Source, base),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn urls(links: Vec<Link>) -> Vec<(Url, LinkKind)> {
+        links
+            .into_iter()
+            .map(|link| (link.url, link.kind))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_rss_and_atom_links() {
+        let rss = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <link>http://example.com/</link>
+    <item>
+      <link> /posts/one </link>
+      <enclosure url="/posts/one.mp3" type="audio/mpeg" length="1024"/>
+    </item>
+  </channel>
+</rss>"#;
+        let atom = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <link rel="self" href="/feed.atom"/>
+  <entry><link href="http://other.com/two"/></entry>
+</feed>"#;
+        let base = to_url("http://example.com/feed");
+
+        assert_eq!(
+            urls(FeedExtractor.extract_links(rss, &base)),
+            vec![
+                (to_url("http://example.com/"), LinkKind::Anchor),
+                (to_url("http://example.com/posts/one"), LinkKind::Anchor),
+                (to_url("http://example.com/posts/one.mp3"), LinkKind::Source),
+            ]
+        );
+        assert_eq!(
+            urls(FeedExtractor.extract_links(atom, &base)),
+            vec![
+                (to_url("http://example.com/feed.atom"), LinkKind::Anchor),
+                (to_url("http://other.com/two"), LinkKind::Anchor),
+            ]
+        );
+        assert_eq!(FeedExtractor.extract_links("not xml", &base), vec![]);
+    }
+}
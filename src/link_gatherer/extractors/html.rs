@@ -0,0 +1,97 @@
+use scraper::{ElementRef, Html, Selector};
+use url::Url;
+
+use super::{
+    super::{
+        link_gatherer::{get_robots_directives, Alternate, PageRecord},
+        links::extract_links,
+    },
+    format_link_as_url, Link, LinkExtractor,
+};
+
+fn get_base_url(html: &Html, url: &Url) -> Url {
+    html.select(&Selector::parse("base[href]").unwrap())
+        .next()
+        .and_then(|base| base.attr("href"))
+        .and_then(|href| format_link_as_url(href, url))
+        .unwrap_or_else(|| url.clone())
+}
+
+// the whitespace-collapsed text of the first matching element, if it has any
+fn get_text(html: &Html, selector: &str) -> Option<String> {
+    html.select(&Selector::parse(selector).unwrap())
+        .next()
+        .map(|element| element.text().collect::<Vec<_>>().join(" "))
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|text| !text.is_empty())
+}
+
+fn get_meta(html: &Html, name: &str) -> Option<String> {
+    html.select(&Selector::parse("meta[name][content]").unwrap())
+        .find(|meta| {
+            meta.attr("name")
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+        .and_then(|meta| meta.attr("content"))
+        .map(|content| content.trim().to_string())
+}
+
+fn get_link_elements<'a>(html: &'a Html, rel: &str) -> Vec<ElementRef<'a>> {
+    html.select(&Selector::parse("link[rel][href]").unwrap())
+        .filter(|link| {
+            link.attr("rel").is_some_and(|rels| {
+                rels.split_ascii_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case(rel))
+            })
+        })
+        .collect()
+}
+
+/// Links in HTML, as described by `LinkKind`, and the page's title, description, canonical URL,
+/// meta robots and alternates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlExtractor;
+
+impl LinkExtractor for HtmlExtractor {
+    fn content_types(&self) -> &[&str] {
+        &["text/html", "application/xhtml+xml"]
+    }
+
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link> {
+        let html = Html::parse_document(body);
+        extract_links(&html, &get_base_url(&html, base))
+    }
+
+    fn extract(&self, body: &str, url: &Url, record: PageRecord) -> PageRecord {
+        read_html(body, url, record)
+    }
+}
+
+// fills in what's read from the HTML of a page
+fn read_html(body: &str, url: &Url, record: PageRecord) -> PageRecord {
+    let html = Html::parse_document(body);
+    let base = get_base_url(&html, url);
+    let robots = get_meta(&html, "robots");
+    let (noindex, nofollow) = get_robots_directives(robots.as_deref(), &record.robots_tag);
+    PageRecord {
+        links: extract_links(&html, &base),
+        title: get_text(&html, "title"),
+        description: get_meta(&html, "description"),
+        canonical: get_link_elements(&html, "canonical")
+            .into_iter()
+            .find_map(|link| format_link_as_url(link.attr("href")?, &base)),
+        robots,
+        noindex,
+        nofollow,
+        alternates: get_link_elements(&html, "alternate")
+            .into_iter()
+            .filter_map(|link| {
+                Some(Alternate {
+                    hreflang: link.attr("hreflang")?.to_string(),
+                    url: format_link_as_url(link.attr("href")?, &base)?,
+                })
+            })
+            .collect(),
+        ..record
+    }
+}
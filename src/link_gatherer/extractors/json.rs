@@ -0,0 +1,107 @@
+use serde_json::Value;
+use url::Url;
+
+use super::{to_link, Link, LinkExtractor, LinkKind};
+
+// the values at a JSON pointer, where `*` matches every item of an array or object
+fn resolve<'a>(value: &'a Value, pointer: &str) -> Vec<&'a Value> {
+    let mut values = vec![value];
+    for token in pointer.split('/').skip(1) {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        values = values
+            .into_iter()
+            .flat_map(|value| match (value, token.as_str()) {
+                (Value::Array(items), "*") => items.iter().collect(),
+                (Value::Object(fields), "*") => fields.values().collect(),
+                (Value::Array(items), index) => index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get(index))
+                    .into_iter()
+                    .collect(),
+                (Value::Object(fields), name) => fields.get(name).into_iter().collect(),
+                _ => vec![],
+            })
+            .collect();
+    }
+    values
+}
+
+/// The strings, or arrays of strings, at each of its JSON pointers (RFC 6901), as anchors. A `*`
+/// token matches every item of an array or object e.g. `/items/*/url`.
+#[derive(Debug, Clone, Default)]
+pub struct JsonExtractor {
+    pub pointers: Vec<String>,
+}
+
+impl LinkExtractor for JsonExtractor {
+    fn content_types(&self) -> &[&str] {
+        &["application/json", "application/ld+json"]
+    }
+
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link> {
+        let json: Value = match serde_json::from_str(body) {
+            Ok(json) => json,
+            Err(err) => {
+                tracing::debug!("Unable to parse JSON - {}", err);
+                return vec![];
+            }
+        };
+        self.pointers
+            .iter()
+            .flat_map(|pointer| resolve(&json, pointer))
+            .flat_map(|value| match value {
+                Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+                value => value.as_str().into_iter().collect::<Vec<_>>(),
+            })
+            .filter_map(|link| to_link(link, LinkKind::Anchor, base))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    #[test]
+    fn extracts_links_at_pointers() {
+        let json = r#"{
+  "next": "/api/items?page=2",
+  "items": [
+    { "url": "/api/items/1", "tags": ["/api/tags/a", 3] },
+    { "url": "/api/items/2", "tags": [] },
+    { "id": 3 }
+  ],
+  "links": { "self": "/api/items", "a/b": "/api/a-b" }
+}"#;
+        let extractor = JsonExtractor {
+            pointers: vec![
+                "/next".to_string(),
+                "/items/*/url".to_string(),
+                "/items/0/tags".to_string(),
+                "/links/a~1b".to_string(),
+                "/missing/*".to_string(),
+            ],
+        };
+        assert_eq!(
+            extractor
+                .extract_links(json, &to_url("http://example.com/api/items"))
+                .into_iter()
+                .map(|link| link.url)
+                .collect::<Vec<_>>(),
+            vec![
+                to_url("http://example.com/api/items?page=2"),
+                to_url("http://example.com/api/items/1"),
+                to_url("http://example.com/api/items/2"),
+                to_url("http://example.com/api/tags/a"),
+                to_url("http://example.com/api/a-b"),
+            ]
+        );
+        assert_eq!(
+            extractor.extract_links("{", &to_url("http://example.com")),
+            vec![]
+        );
+    }
+}
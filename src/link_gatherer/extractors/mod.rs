@@ -0,0 +1,139 @@
+mod css;
+mod feed;
+mod html;
+mod json;
+mod sitemap;
+mod uri_list;
+pub use css::CssExtractor;
+pub use feed::FeedExtractor;
+pub use html::HtmlExtractor;
+pub use json::JsonExtractor;
+pub use sitemap::SitemapExtractor;
+pub use uri_list::UriListExtractor;
+
+use std::{fmt, sync::Arc};
+
+use url::Url;
+
+use super::{
+    link_gatherer::{format_link_as_url, PageRecord},
+    links::{Link, LinkKind},
+};
+
+/// Gathers the links from a format of document, chosen by the response's Content-Type.
+pub trait LinkExtractor: fmt::Debug + Send + Sync {
+    /// Content types handled, without parameters e.g. `application/rss+xml`
+    fn content_types(&self) -> &[&str];
+
+    /// Every link in the document, resolved against `base`.
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link>;
+
+    /// Fills in the record of the page at `url` from its body, by default with only its links.
+    fn extract(&self, body: &str, url: &Url, record: PageRecord) -> PageRecord {
+        PageRecord {
+            links: self.extract_links(body, url),
+            ..record
+        }
+    }
+}
+
+fn to_link(link: &str, kind: LinkKind, base: &Url) -> Option<Link> {
+    format_link_as_url(link.trim(), base).map(|url| Link {
+        url,
+        kind,
+        nofollow: false,
+    })
+}
+
+/// The extractor used for each content type. Responses of a type without one are recorded
+/// without being downloaded.
+#[derive(Debug, Clone)]
+pub struct ExtractorRegistry {
+    extractors: Vec<Arc<dyn LinkExtractor>>,
+}
+
+/// A registry of just the HTML extractor.
+impl Default for ExtractorRegistry {
+    fn default() -> Self {
+        ExtractorRegistry {
+            extractors: vec![Arc::new(HtmlExtractor)],
+        }
+    }
+}
+
+impl ExtractorRegistry {
+    /// Adds an extractor, which takes precedence over any registered earlier for the same
+    /// content types.
+    pub fn register(&mut self, extractor: impl LinkExtractor + 'static) {
+        self.extractors.push(Arc::new(extractor));
+    }
+
+    /// The extractor for a content type without its parameters.
+    pub fn get(&self, media_type: &str) -> Option<&dyn LinkExtractor> {
+        self.extractors
+            .iter()
+            .rev()
+            .find(|extractor| extractor.content_types().contains(&media_type))
+            .map(Arc::as_ref)
+    }
+
+    /// Every content type with an extractor.
+    pub fn content_types(&self) -> Vec<String> {
+        let mut content_types: Vec<String> = self
+            .extractors
+            .iter()
+            .flat_map(|extractor| extractor.content_types())
+            .map(|content_type| content_type.to_string())
+            .collect();
+        content_types.sort();
+        content_types.dedup();
+        content_types
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(&'static str);
+
+    impl LinkExtractor for Fixed {
+        fn content_types(&self) -> &[&str] {
+            &["text/html", "text/csv"]
+        }
+
+        fn extract_links(&self, _: &str, base: &Url) -> Vec<Link> {
+            to_link(self.0, LinkKind::Anchor, base)
+                .into_iter()
+                .collect()
+        }
+    }
+
+    #[test]
+    fn registry_prefers_later_extractors() {
+        let base = Url::parse("http://example.com/").unwrap();
+        let mut registry = ExtractorRegistry::default();
+        registry.register(FeedExtractor);
+        registry.register(Fixed("/fixed"));
+
+        assert_eq!(
+            registry
+                .get("text/html")
+                .map(|extractor| extractor.extract_links("<a href=\"/a\">a</a>", &base)),
+            Some(vec![to_link("/fixed", LinkKind::Anchor, &base).unwrap()])
+        );
+        assert!(registry.get("application/rss+xml").is_some());
+        assert!(registry.get("image/png").is_none());
+        assert_eq!(
+            registry.content_types(),
+            vec![
+                "application/atom+xml",
+                "application/rss+xml",
+                "application/xhtml+xml",
+                "text/csv",
+                "text/html",
+            ]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use url::Url;
+
+use super::{FeedExtractor, Link, LinkExtractor, LinkKind};
+use crate::sitemap::{self, Sitemap};
+
+/// The pages of a sitemap urlset and the sitemaps of a sitemap index, as anchors. Other XML is
+/// read as a feed, as feeds are often served as plain XML.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SitemapExtractor;
+
+impl LinkExtractor for SitemapExtractor {
+    fn content_types(&self) -> &[&str] {
+        &["application/xml", "text/xml"]
+    }
+
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link> {
+        match sitemap::parse(body.as_bytes()) {
+            Ok(Sitemap::Index(urls) | Sitemap::UrlSet(urls)) => urls
+                .into_iter()
+                .map(|url| Link {
+                    url,
+                    kind: LinkKind::Anchor,
+                    nofollow: false,
+                })
+                .collect(),
+            Err(err) => {
+                tracing::debug!("Not a sitemap, reading as a feed - {}", err);
+                FeedExtractor.extract_links(body, base)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn urls(links: Vec<Link>) -> Vec<Url> {
+        links.into_iter().map(|link| link.url).collect()
+    }
+
+    #[test]
+    fn extracts_sitemap_links_and_falls_back_to_feeds() {
+        let base = to_url("http://example.com/sitemap.xml");
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>http://example.com/sitemap-1.xml</loc></sitemap>
+</sitemapindex>"#;
+        let urlset = r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>http://example.com/one</loc></url>
+</urlset>"#;
+        let feed = "<rss><channel><item><link>/two</link></item></channel></rss>";
+
+        assert_eq!(
+            urls(SitemapExtractor.extract_links(index, &base)),
+            vec![to_url("http://example.com/sitemap-1.xml")]
+        );
+        assert_eq!(
+            urls(SitemapExtractor.extract_links(urlset, &base)),
+            vec![to_url("http://example.com/one")]
+        );
+        assert_eq!(
+            urls(SitemapExtractor.extract_links(feed, &base)),
+            vec![to_url("http://example.com/two")]
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use url::Url;
+
+use super::{to_link, Link, LinkExtractor, LinkKind};
+
+/// A `text/uri-list` of one URL per line with `#` comments, as per RFC 2483.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UriListExtractor;
+
+impl LinkExtractor for UriListExtractor {
+    fn content_types(&self) -> &[&str] {
+        &["text/uri-list"]
+    }
+
+    fn extract_links(&self, body: &str, base: &Url) -> Vec<Link> {
+        body.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| to_link(line, LinkKind::Anchor, base))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    #[test]
+    fn extracts_uri_list_links() {
+        let list = "# pages\r\nhttp://example.com/one\r\n\r\n  /two  \r\nhttp://[::1\r\n";
+        assert_eq!(
+            UriListExtractor
+                .extract_links(list, &to_url("http://example.com/list"))
+                .into_iter()
+                .map(|link| link.url)
+                .collect::<Vec<_>>(),
+            vec![
+                to_url("http://example.com/one"),
+                to_url("http://example.com/two")
+            ]
+        );
+    }
+}
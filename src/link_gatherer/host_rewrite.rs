@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use url::Url;
+
+/// Fetches URLs on one origin from another e.g. a staging server, while they're reported with
+/// the original origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostRewrite {
+    pub from: Url,
+    pub to: Url,
+}
+
+// the URL moved to the other origin if it's on the first one
+fn swap_origin(url: &Url, from: &Url, to: &Url) -> Option<Url> {
+    if url.origin() != from.origin() {
+        return None;
+    }
+    let mut swapped = url.clone();
+    swapped.set_scheme(to.scheme()).ok()?;
+    swapped.set_host(to.host_str()).ok()?;
+    swapped.set_port(to.port()).ok()?;
+    Some(swapped)
+}
+
+impl HostRewrite {
+    /// The URL to fetch in place of `url`, if it's on the rewritten origin.
+    pub fn target(&self, url: &Url) -> Option<Url> {
+        swap_origin(url, &self.from, &self.to)
+    }
+
+    /// The URL reported in place of one on the origin fetched from, such as a redirect's Location.
+    pub fn canonical(&self, url: &Url) -> Option<Url> {
+        swap_origin(url, &self.to, &self.from)
+    }
+}
+
+/// Parses `from=to` where both are origins e.g. `https://www.example.com=http://10.0.0.5:8080`.
+impl FromStr for HostRewrite {
+    type Err = String;
+
+    fn from_str(rewrite: &str) -> Result<Self, Self::Err> {
+        let origin = |origin: &str| match Url::parse(origin) {
+            Ok(url) if url.has_host() && matches!(url.scheme(), "http" | "https") => Ok(url),
+            _ => Err(format!("{} isn't an http or https origin", origin)),
+        };
+        let (from, to) = rewrite
+            .split_once('=')
+            .ok_or_else(|| "expected `from=to`".to_string())?;
+        Ok(HostRewrite {
+            from: origin(from)?,
+            to: origin(to)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    #[test]
+    fn rewrites_urls_on_the_origin() {
+        let rewrite: HostRewrite = "https://www.example.com=http://127.0.0.1:8080"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rewrite.target(&to_url("https://www.example.com/docs/?page=2#top")),
+            Some(to_url("http://127.0.0.1:8080/docs/?page=2#top"))
+        );
+        assert_eq!(rewrite.target(&to_url("http://www.example.com/")), None);
+        assert_eq!(rewrite.target(&to_url("https://example.com/")), None);
+        assert_eq!(
+            rewrite.canonical(&to_url("http://127.0.0.1:8080/new")),
+            Some(to_url("https://www.example.com/new"))
+        );
+        assert_eq!(rewrite.canonical(&to_url("http://127.0.0.1/new")), None);
+    }
+
+    #[test]
+    fn parses_origins() {
+        assert_eq!(
+            "http://a.test=https://b.test:8443".parse(),
+            Ok(HostRewrite {
+                from: to_url("http://a.test"),
+                to: to_url("https://b.test:8443"),
+            })
+        );
+        assert!("http://a.test".parse::<HostRewrite>().is_err());
+        assert!("http://a.test=mailto:b@test"
+            .parse::<HostRewrite>()
+            .is_err());
+    }
+}
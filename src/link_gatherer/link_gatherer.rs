@@ -1,83 +1,396 @@
-use std::future::Future;
+use std::{future::Future, time::Duration};
 
-use super::{url_content_getter::URLContentGetterError, URLContentGetter};
-use scraper::{Html, Selector};
+use super::{
+    extractors::ExtractorRegistry,
+    links::{Link, LinkKind},
+    login::{fill_form, Login},
+    url_content_getter::{
+        media_type, BodyPolicy, Conditions, HttpClient, RedirectHop, Response,
+        URLContentGetterError,
+    },
+    URLContentGetter,
+};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// An alternate version of a page for another language or region, from `<link rel="alternate" hreflang>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alternate {
+    pub hreflang: String,
+    pub url: Url,
+}
+
+/// The links found on a page along with what is known about the page itself.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PageRecord {
+    pub links: Vec<Link>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    pub response_time: Duration,
+    pub final_url: Option<Url>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub canonical: Option<Url>,
+    /// The content of `<meta name="robots">` e.g. `noindex, follow`
+    pub robots: Option<String>,
+    /// The value of each X-Robots-Tag header
+    pub robots_tag: Vec<String>,
+    /// Whether the meta robots or X-Robots-Tag asks for the page not to be indexed
+    pub noindex: bool,
+    /// Whether the meta robots or X-Robots-Tag asks for none of the page's links to be followed
+    pub nofollow: bool,
+    pub alternates: Vec<Alternate>,
+    pub crawled_at: Timestamp,
+    pub last_modified: Option<Timestamp>,
+    pub etag: Option<String>,
+    /// Whether the page hadn't changed since the previous crawl, so this record was reused
+    pub unchanged: bool,
+    /// Set instead of the rest when the page redirects elsewhere
+    pub redirect: Option<RedirectHop>,
+    /// The redirects followed to reach the page when the client follows them, with the page at
+    /// the last one's location
+    pub redirects: Vec<RedirectHop>,
+}
+
+/// A record of a page whose links are all anchors.
+impl From<Vec<Url>> for PageRecord {
+    fn from(urls: Vec<Url>) -> Self {
+        PageRecord {
+            links: urls
+                .into_iter()
+                .map(|url| Link {
+                    url,
+                    kind: LinkKind::Anchor,
+                    nofollow: false,
+                })
+                .collect(),
+            ..PageRecord::default()
+        }
+    }
+}
+
+impl PageRecord {
+    pub fn urls(&self) -> impl Iterator<Item = &Url> {
+        self.links.iter().map(|link| &link.url)
+    }
+}
 
 pub trait LinkGatherer: Send + Sync + Clone {
     fn get_links(
         &mut self,
-        url: &str,
-    ) -> impl Future<Output = Result<Vec<String>, URLContentGetterError>> + Send;
+        url: &Url,
+    ) -> impl Future<Output = Result<PageRecord, URLContentGetterError>> + Send;
+
+    /// Gathers the links only if the page has changed since `previous` was recorded, returning
+    /// `None` when it hasn't.
+    fn get_links_if_changed(
+        &mut self,
+        url: &Url,
+        _previous: &PageRecord,
+    ) -> impl Future<Output = Result<Option<PageRecord>, URLContentGetterError>> + Send {
+        let links = self.get_links(url);
+        async move { links.await.map(Some) }
+    }
+
+    fn get_content(
+        &mut self,
+        url: &Url,
+    ) -> impl Future<Output = Result<String, URLContentGetterError>> + Send;
+
+    fn get_content_bytes(
+        &mut self,
+        url: &Url,
+    ) -> impl Future<Output = Result<Vec<u8>, URLContentGetterError>> + Send;
+
+    /// Checks the URL responds successfully without gathering its links.
+    fn check_link(
+        &mut self,
+        url: &Url,
+    ) -> impl Future<Output = Result<u16, URLContentGetterError>> + Send;
+
+    /// Submits the login form, after which requests are made with the session it starts.
+    fn log_in(
+        &mut self,
+        login: &Login,
+    ) -> impl Future<Output = Result<(), URLContentGetterError>> + Send;
+}
+
+/// Resolves a link found on a page against that page's base URL as per RFC 3986.
+pub fn format_link_as_url(link: &str, base: &Url) -> Option<Url> {
+    match base.join(link) {
+        Ok(url) => Some(url),
+        Err(err) => {
+            tracing::debug!("Unable to resolve link {:?} - {}", link, err);
+            None
+        }
+    }
+}
+
+// Directives with a value, which otherwise look like an X-Robots-Tag naming a crawler
+const VALUED_DIRECTIVES: [&str; 4] = [
+    "unavailable_after",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+];
+
+// whether an X-Robots-Tag only applies to a named crawler e.g. `otherbot: noindex`
+fn names_crawler(robots_tag: &str) -> bool {
+    robots_tag.split_once(':').is_some_and(|(name, _)| {
+        let name = name.trim().to_ascii_lowercase();
+        !name.contains([' ', ',']) && !VALUED_DIRECTIVES.contains(&name.as_str())
+    })
+}
+
+// whether the meta robots and X-Robots-Tags not naming a crawler include noindex and nofollow
+pub(super) fn get_robots_directives(meta: Option<&str>, robots_tag: &[String]) -> (bool, bool) {
+    let directives: Vec<String> = meta
+        .into_iter()
+        .chain(
+            robots_tag
+                .iter()
+                .map(String::as_str)
+                .filter(|tag| !names_crawler(tag)),
+        )
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .collect();
+    let has = |name: &str| {
+        directives
+            .iter()
+            .any(|directive| directive == name || directive == "none")
+    };
+    (has("noindex"), has("nofollow"))
 }
 
+/// Gathers links from the documents its extractors handle, recording any other resource
+/// without downloading it.
 #[derive(Clone, Debug)]
-pub struct Page<T = reqwest::Client> {
+pub struct Page<T = HttpClient> {
     client: T,
+    extractors: ExtractorRegistry,
+    max_body_size: Option<u64>,
 }
 
 impl<T: URLContentGetter + Clone> Page<T> {
+    /// A page only gathering links from HTML.
     pub fn new(client: T) -> Self {
-        Page { client }
+        Page {
+            client,
+            extractors: ExtractorRegistry::default(),
+            max_body_size: None,
+        }
+    }
+
+    /// Abandons pages whose body is larger than `max_size` bytes.
+    pub fn with_max_body_size(self, max_size: u64) -> Self {
+        Page {
+            max_body_size: Some(max_size),
+            ..self
+        }
+    }
+
+    pub fn with_extractors(self, extractors: ExtractorRegistry) -> Self {
+        Page { extractors, ..self }
+    }
+
+    fn body_policy(&self) -> BodyPolicy {
+        BodyPolicy {
+            content_types: Some(self.extractors.content_types()),
+            max_size: self.max_body_size,
+        }
+    }
+}
+
+impl<T: URLContentGetter + Clone + Send + Sync> Page<T> {
+    // the record of the page, or `None` when it's unchanged since the conditions were recorded
+    async fn gather(
+        &self,
+        url: &Url,
+        conditions: &Conditions,
+    ) -> Result<Option<PageRecord>, URLContentGetterError> {
+        match self
+            .client
+            .get_http_response(url, &self.body_policy(), conditions)
+            .await
+        {
+            Ok(Response { status: 304, .. }) => Ok(None),
+            Ok(Response {
+                redirect: Some(redirect),
+                ..
+            }) => Ok(Some(PageRecord {
+                redirect: Some(redirect),
+                ..PageRecord::default()
+            })),
+            Ok(response) => {
+                let (noindex, nofollow) = get_robots_directives(None, &response.robots_tag);
+                let media_type = response.content_type.as_deref().map(media_type);
+                let record = PageRecord {
+                    status: response.status,
+                    content_type: response.content_type,
+                    content_length: response.content_length,
+                    response_time: response.response_time,
+                    final_url: response.final_url,
+                    robots_tag: response.robots_tag,
+                    noindex,
+                    nofollow,
+                    crawled_at: response.fetched_at,
+                    last_modified: response.last_modified,
+                    etag: response.etag,
+                    redirects: response.redirects,
+                    ..PageRecord::default()
+                };
+                // links are relative to where any redirects the client followed led
+                let base = record.final_url.clone().unwrap_or_else(|| url.clone());
+                // pages without a Content-Type are most likely HTML
+                let record = match self
+                    .extractors
+                    .get(media_type.as_deref().unwrap_or("text/html"))
+                {
+                    Some(extractor) => extractor.extract(&response.body, &base, record),
+                    None => {
+                        tracing::info!("Not gathering links from {:?}", media_type);
+                        record
+                    }
+                };
+                tracing::info!("Found {} links", record.links.len());
+                tracing::debug!("Links {:?}", record.links);
+                Ok(Some(record))
+            }
+            Err(err) => Err(err),
+        }
     }
 }
 
 impl<T: URLContentGetter + Clone + Send + Sync> LinkGatherer for Page<T> {
     #[tracing::instrument(skip(self))]
-    fn get_links(
+    async fn get_links(&mut self, url: &Url) -> Result<PageRecord, URLContentGetterError> {
+        let record = self.gather(url, &Conditions::default()).await?;
+        Ok(record.unwrap_or_default())
+    }
+
+    #[tracing::instrument(skip(self, previous))]
+    async fn get_links_if_changed(
         &mut self,
-        url: &str,
-    ) -> impl Future<Output = Result<Vec<String>, URLContentGetterError>> + Send {
-        async move {
-            let url = url.to_string();
-
-            match self.client.get_http_response_body(&url).await {
-                Ok(text) => {
-                    let html = Html::parse_document(&text);
-                    let links = html
-                        .select(&Selector::parse("a").unwrap())
-                        .into_iter()
-                        .flat_map(|f| match f.attr("href") {
-                            Some(href) => vec![href.to_string()],
-                            _ => vec![],
-                        })
-                        .collect::<Vec<_>>();
-                    tracing::info!("Found {} links", links.len());
-                    tracing::debug!("Links {:?}", links);
-                    Ok(links)
-                }
-                Err(err) => Err(err),
-            }
-        }
+        url: &Url,
+        previous: &PageRecord,
+    ) -> Result<Option<PageRecord>, URLContentGetterError> {
+        let conditions = Conditions {
+            etag: previous.etag.clone(),
+            last_modified: previous.last_modified,
+        };
+        self.gather(url, &conditions).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_content(&mut self, url: &Url) -> Result<String, URLContentGetterError> {
+        self.client.get_http_response_body(url).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_content_bytes(&mut self, url: &Url) -> Result<Vec<u8>, URLContentGetterError> {
+        self.client.get_http_response_bytes(url).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn check_link(&mut self, url: &Url) -> Result<u16, URLContentGetterError> {
+        self.client.get_http_status(url).await
+    }
+
+    #[tracing::instrument(skip_all, fields(url = login.url.to_string()))]
+    async fn log_in(&mut self, login: &Login) -> Result<(), URLContentGetterError> {
+        let body = self.client.get_http_response_body(&login.url).await?;
+        let (action, fields) = fill_form(&body, &login.url, login)?;
+        let status = self.client.post_form(&action, &fields).await?;
+        tracing::info!("Submitted login form to {} - {}", action, status);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::URLContentGetter;
-    use crate::link_gatherer::url_content_getter::URLContentGetterError;
-    use std::collections::HashMap;
+    use crate::link_gatherer::{
+        extractors::{FeedExtractor, UriListExtractor},
+        url_content_getter::{BodyPolicy, Conditions, Response, URLContentGetterError},
+    };
+    use crate::test_support::to_url;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+    use url::Url;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    type Form = (Url, Vec<(String, String)>);
 
     #[derive(Clone)]
     pub struct MockURLCG {
-        map: HashMap<String, Result<String, URLContentGetterError>>,
+        map: HashMap<Url, Result<Response, URLContentGetterError>>,
+        forms: Arc<Mutex<Vec<Form>>>,
     }
 
     impl MockURLCG {
-        pub fn new(map: HashMap<String, Result<String, URLContentGetterError>>) -> Self {
-            MockURLCG { map }
+        pub fn new(map: HashMap<Url, Result<String, URLContentGetterError>>) -> Self {
+            MockURLCG {
+                map: map
+                    .into_iter()
+                    .map(|(url, content)| (url, content.map(Response::from)))
+                    .collect(),
+                forms: Arc::new(Mutex::new(vec![])),
+            }
+        }
+
+        pub fn with_response(mut self, url: Url, response: Response) -> Self {
+            self.map.insert(url, Ok(response));
+            self
         }
     }
 
     impl URLContentGetter for MockURLCG {
-        async fn get_http_response_body(&self, url: &str) -> Result<String, URLContentGetterError> {
+        async fn get_http_response(
+            &self,
+            url: &Url,
+            body: &BodyPolicy,
+            conditions: &Conditions,
+        ) -> Result<Response, URLContentGetterError> {
             match self.map.get(url) {
-                Some(x) => match x {
-                    Ok(content) => Ok(content.clone()),
-                    Err(err) => Err(err.clone()),
-                },
-                None => Ok("".to_string()),
+                Some(Ok(response))
+                    if conditions.etag.is_some() && conditions.etag == response.etag =>
+                {
+                    Ok(Response {
+                        status: 304,
+                        ..Response::default()
+                    })
+                }
+                Some(Ok(response)) => Ok(Response {
+                    body: match body.reads(response.content_type.as_deref()) {
+                        true => response.body.clone(),
+                        false => String::new(),
+                    },
+                    ..response.clone()
+                }),
+                Some(Err(err)) => Err(err.clone()),
+                None => Ok(Response::default()),
             }
         }
+
+        async fn post_form(
+            &self,
+            url: &Url,
+            fields: &[(String, String)],
+        ) -> Result<u16, URLContentGetterError> {
+            self.forms
+                .lock()
+                .unwrap()
+                .push((url.clone(), fields.to_vec()));
+            Ok(200)
+        }
     }
 
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -85,7 +398,7 @@ mod tests {
 
     #[tokio::test]
     async fn link_gatherer_happy_path() {
-        let url = "https://example.com";
+        let url = to_url("https://example.com");
         let html = r#"
 <html>
   <body>
@@ -95,31 +408,319 @@ mod tests {
     <a href="/three/four?hello=there">home</a>
   </body>
 </html>"#;
-        let mucg = MockURLCG::new(HashMap::from([(url.to_string(), Ok(html.to_string()))]));
+        let mucg = MockURLCG::new(HashMap::from([(url.clone(), Ok(html.to_string()))]));
         let mut page = Page::new(mucg);
-        let links = page.get_links(url).await;
+        let links = page.get_links(&url).await;
         assert_eq!(
-            links.unwrap(),
+            links.unwrap().urls().cloned().collect::<Vec<_>>(),
             vec![
-                "https://www.example.com".to_string(),
-                "https://www.example.com/one".to_string(),
-                "two".to_string(),
-                "/three/four?hello=there".to_string()
+                to_url("https://www.example.com"),
+                to_url("https://www.example.com/one"),
+                to_url("https://example.com/two"),
+                to_url("https://example.com/three/four?hello=there")
             ]
         )
     }
 
+    #[tokio::test]
+    async fn link_gatherer_resolves_relative_to_nested_page() {
+        let url = to_url("https://example.com/docs/guide/index.html?page=1");
+        let html = r##"
+<html>
+  <body>
+    <a href="../intro">intro</a>
+    <a href="./setup">setup</a>
+    <a href="usage">usage</a>
+    <a href="?page=2">next</a>
+    <a href="#install">install</a>
+    <a href="//cdn.example.com/file">file</a>
+    <a href="/root">root</a>
+    <a href="http://[::1">broken</a>
+  </body>
+</html>"##;
+        let mucg = MockURLCG::new(HashMap::from([(url.clone(), Ok(html.to_string()))]));
+        let mut page = Page::new(mucg);
+        let links = page.get_links(&url).await;
+        assert_eq!(
+            links.unwrap().urls().cloned().collect::<Vec<_>>(),
+            vec![
+                to_url("https://example.com/docs/intro"),
+                to_url("https://example.com/docs/guide/setup"),
+                to_url("https://example.com/docs/guide/usage"),
+                to_url("https://example.com/docs/guide/index.html?page=2"),
+                to_url("https://example.com/docs/guide/index.html?page=1#install"),
+                to_url("https://cdn.example.com/file"),
+                to_url("https://example.com/root"),
+            ]
+        )
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_resolves_relative_to_page_redirected_to() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/a"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", "/dir/b"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/dir/b"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"<a href="c">c</a>"#, "text/html"),
+            )
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let mut page = Page::new(HttpClient::default());
+        let links = page.get_links(&root.join("/a").unwrap()).await;
+        assert_eq!(
+            links.unwrap().urls().cloned().collect::<Vec<_>>(),
+            vec![root.join("/dir/c").unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_honours_base_href() {
+        let url = to_url("https://example.com/docs/guide/");
+        let html = r#"
+<html>
+  <head>
+    <base href="/static/v2/">
+  </head>
+  <body>
+    <a href="page">page</a>
+    <a href="../other">other</a>
+    <a href="/absolute">absolute</a>
+  </body>
+</html>"#;
+        let mucg = MockURLCG::new(HashMap::from([(url.clone(), Ok(html.to_string()))]));
+        let mut page = Page::new(mucg);
+        let links = page.get_links(&url).await;
+        assert_eq!(
+            links.unwrap().urls().cloned().collect::<Vec<_>>(),
+            vec![
+                to_url("https://example.com/static/v2/page"),
+                to_url("https://example.com/static/other"),
+                to_url("https://example.com/absolute"),
+            ]
+        )
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_records_page_metadata() {
+        let url = to_url("https://example.com/docs/");
+        let html = r#"
+<html>
+  <head>
+    <title>
+      Docs |
+      Example
+    </title>
+    <meta name="Description" content=" All about the docs ">
+    <meta name="robots" content="noindex, follow">
+    <link rel="canonical" href="/docs">
+    <link rel="alternate" hreflang="fr" href="/fr/docs/">
+    <link rel="alternate" type="application/rss+xml" href="/feed">
+    <link rel="stylesheet alternate" hreflang="de" href="https://de.example.com/docs/">
+  </head>
+  <body><a href="one">one</a></body>
+</html>"#;
+        let mucg = MockURLCG::new(HashMap::from([(url.clone(), Ok(html.to_string()))]));
+        let mut page = Page::new(mucg);
+        let record = page.get_links(&url).await.unwrap();
+        assert_eq!(record.title.as_deref(), Some("Docs | Example"));
+        assert_eq!(record.description.as_deref(), Some("All about the docs"));
+        assert_eq!(record.robots.as_deref(), Some("noindex, follow"));
+        assert!(record.noindex);
+        assert!(!record.nofollow);
+        assert_eq!(record.canonical, Some(to_url("https://example.com/docs")));
+        assert_eq!(
+            record.alternates,
+            vec![
+                Alternate {
+                    hreflang: "fr".to_string(),
+                    url: to_url("https://example.com/fr/docs/"),
+                },
+                Alternate {
+                    hreflang: "de".to_string(),
+                    url: to_url("https://de.example.com/docs/"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_uses_extractor_for_content_type() {
+        let feed = to_url("https://example.com/feed");
+        let list = to_url("https://example.com/urls");
+        let mucg = MockURLCG::new(HashMap::new())
+            .with_response(
+                feed.clone(),
+                Response {
+                    body: "<rss><channel><item><link>/one</link></item></channel></rss>"
+                        .to_string(),
+                    content_type: Some("application/rss+xml; charset=utf-8".to_string()),
+                    ..Response::default()
+                },
+            )
+            .with_response(
+                list.clone(),
+                Response {
+                    body: "/two".to_string(),
+                    content_type: Some("text/uri-list".to_string()),
+                    ..Response::default()
+                },
+            );
+
+        let mut extractors = ExtractorRegistry::default();
+        extractors.register(FeedExtractor);
+        let mut page = Page::new(mucg.clone()).with_extractors(extractors.clone());
+        let record = page.get_links(&feed).await.unwrap();
+        assert_eq!(
+            record.urls().cloned().collect::<Vec<_>>(),
+            vec![to_url("https://example.com/one")]
+        );
+        assert_eq!(page.get_links(&list).await.unwrap().links, vec![]);
+        extractors.register(UriListExtractor);
+        let mut page = page.with_extractors(extractors);
+        assert_eq!(
+            page.get_links(&list)
+                .await
+                .unwrap()
+                .urls()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![to_url("https://example.com/two")]
+        );
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_records_other_resources_as_leaves() {
+        let url = to_url("https://example.com/report.pdf");
+        let mucg = MockURLCG::new(HashMap::new()).with_response(
+            url.clone(),
+            Response {
+                body: "<a href=\"/one\">one</a>".to_string(),
+                status: 200,
+                content_type: Some("application/pdf".to_string()),
+                content_length: Some(2048),
+                robots_tag: vec!["noindex".to_string()],
+                ..Response::default()
+            },
+        );
+        let mut page = Page::new(mucg);
+        assert_eq!(
+            page.get_links(&url).await,
+            Ok(PageRecord {
+                status: 200,
+                content_type: Some("application/pdf".to_string()),
+                content_length: Some(2048),
+                robots_tag: vec!["noindex".to_string()],
+                noindex: true,
+                ..PageRecord::default()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_skips_unchanged_pages() {
+        let url = to_url("https://example.com");
+        let mucg = MockURLCG::new(HashMap::new()).with_response(
+            url.clone(),
+            Response {
+                body: "<a href=\"/one\">one</a>".to_string(),
+                status: 200,
+                content_type: Some("text/html".to_string()),
+                etag: Some("\"v2\"".to_string()),
+                ..Response::default()
+            },
+        );
+        let mut page = Page::new(mucg);
+        let record = page
+            .get_links_if_changed(&url, &PageRecord::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.etag, Some("\"v2\"".to_string()));
+        assert_eq!(
+            record.urls().cloned().collect::<Vec<_>>(),
+            vec![to_url("https://example.com/one")]
+        );
+        assert_eq!(page.get_links_if_changed(&url, &record).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn link_gatherer_submits_login_form() {
+        let url = to_url("https://example.com/login");
+        let html = r#"
+<form action="/session" method="post">
+  <input type="hidden" name="csrf" value="t0k3n">
+  <input name="username"><input type="password" name="password">
+</form>"#;
+        let mucg = MockURLCG::new(HashMap::from([(url.clone(), Ok(html.to_string()))]));
+        let forms = mucg.forms.clone();
+        let fields = |fields: &[(&str, &str)]| {
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut page = Page::new(mucg);
+        let login = Login {
+            url,
+            form_selector: "form".to_string(),
+            fields: fields(&[("username", "admin"), ("password", "secret")]),
+        };
+        assert_eq!(page.log_in(&login).await, Ok(()));
+        assert_eq!(
+            *forms.lock().unwrap(),
+            vec![(
+                to_url("https://example.com/session"),
+                fields(&[
+                    ("csrf", "t0k3n"),
+                    ("username", "admin"),
+                    ("password", "secret")
+                ])
+            )]
+        );
+    }
+
+    #[test]
+    fn reads_robots_directives() {
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        assert_eq!(get_robots_directives(None, &[]), (false, false));
+        assert_eq!(
+            get_robots_directives(Some("NoIndex, NoFollow"), &[]),
+            (true, true)
+        );
+        assert_eq!(get_robots_directives(Some("none"), &[]), (true, true));
+        assert_eq!(
+            get_robots_directives(Some("index"), &tags(&["nofollow"])),
+            (false, true)
+        );
+        assert_eq!(
+            get_robots_directives(None, &tags(&["otherbot: noindex, nofollow"])),
+            (false, false)
+        );
+        assert_eq!(
+            get_robots_directives(None, &tags(&["unavailable_after: 2025-01-01, noindex"])),
+            (true, false)
+        );
+    }
+
     #[tokio::test]
     async fn link_gatherer_returns_error() {
-        let url = "https://example.com";
+        let url = to_url("https://example.com");
 
         let mucg = MockURLCG::new(HashMap::from([(
-            url.to_string(),
+            url.clone(),
             Err(URLContentGetterError::Request(404)),
         )]));
         let mut page = Page::new(mucg);
-        match page.get_links(url).await {
-            Ok(_) => assert!(false, "should throw error"),
+        match page.get_links(&url).await {
+            Ok(_) => panic!("should throw error"),
             Err(err) => assert_eq!(err, URLContentGetterError::Request(404)),
         }
     }
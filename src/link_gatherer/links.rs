@@ -0,0 +1,311 @@
+use clap::ValueEnum;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::link_gatherer::format_link_as_url;
+
+/// Where on a page a link was found, which decides whether it leads to another page or is a
+/// resource used by the page.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+pub enum LinkKind {
+    /// `<a href>`
+    Anchor,
+    /// `<area href>`
+    Area,
+    /// `<iframe src>` and `<frame src>`
+    Frame,
+    /// `<form action>`
+    Form,
+    /// `<meta http-equiv="refresh">`
+    Refresh,
+    /// `<link href>` e.g. stylesheets and icons
+    Link,
+    /// `<img src>` and `<img srcset>`
+    Image,
+    /// `<script src>`
+    Script,
+    /// `<source src>` and `<source srcset>`
+    Source,
+    /// `url()` in `<style>` elements and style attributes
+    Style,
+}
+
+impl LinkKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkKind::Anchor => "anchor",
+            LinkKind::Area => "area",
+            LinkKind::Frame => "frame",
+            LinkKind::Form => "form",
+            LinkKind::Refresh => "refresh",
+            LinkKind::Link => "link",
+            LinkKind::Image => "image",
+            LinkKind::Script => "script",
+            LinkKind::Source => "source",
+            LinkKind::Style => "style",
+        }
+    }
+
+    /// Whether the link leads to another page rather than a resource used by this one.
+    pub fn is_navigation(&self) -> bool {
+        matches!(
+            self,
+            LinkKind::Anchor
+                | LinkKind::Area
+                | LinkKind::Frame
+                | LinkKind::Form
+                | LinkKind::Refresh
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Link {
+    pub url: Url,
+    pub kind: LinkKind,
+    /// Whether the element has `rel="nofollow"`
+    pub nofollow: bool,
+}
+
+/// What the tracer does with a link of a given kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkAction {
+    Ignore,
+    /// Request it to check it responds without gathering its links
+    Check,
+    /// Gather its links when it's in scope, otherwise treat it as an external link
+    Follow,
+}
+
+/// Which kinds of link are followed and which are only checked. Any others are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkPolicy {
+    pub follow: Vec<LinkKind>,
+    pub check: Vec<LinkKind>,
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        // forms are often only meant to be posted to so aren't followed by default, and the
+        // resources a page uses are only checked
+        LinkPolicy {
+            follow: LinkKind::value_variants()
+                .iter()
+                .copied()
+                .filter(|kind| kind.is_navigation() && *kind != LinkKind::Form)
+                .collect(),
+            check: LinkKind::value_variants()
+                .iter()
+                .copied()
+                .filter(|kind| !kind.is_navigation())
+                .collect(),
+        }
+    }
+}
+
+impl LinkPolicy {
+    pub fn action(&self, kind: LinkKind) -> LinkAction {
+        if self.follow.contains(&kind) {
+            LinkAction::Follow
+        } else if self.check.contains(&kind) {
+            LinkAction::Check
+        } else {
+            LinkAction::Ignore
+        }
+    }
+}
+
+const SELECTOR: &str = "a[href], area[href], iframe[src], frame[src], form[action], \
+    meta[http-equiv][content], link[href], img[src], img[srcset], script[src], \
+    source[src], source[srcset], style, [style]";
+
+// the URLs of a srcset e.g. `small.jpg 480w, large.jpg 1080w`
+fn srcset_urls(srcset: &str) -> impl Iterator<Item = &str> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| candidate.split_whitespace().next())
+}
+
+// the URL of a refresh e.g. `5; url='/next'`
+fn refresh_url(content: &str) -> Option<&str> {
+    let (_, target) = content.split_once(';')?;
+    let target = target.trim_start();
+    let url = match target.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url=") => &target[4..],
+        _ => target,
+    };
+    Some(url.trim().trim_matches(['\'', '"']))
+}
+
+// the URLs of every `url()` in some CSS
+pub(super) fn css_urls(css: &str) -> Vec<&str> {
+    css.match_indices("url(")
+        .filter_map(|(start, _)| {
+            let rest = &css[start + 4..];
+            rest.find(')')
+                .map(|end| rest[..end].trim().trim_matches(['\'', '"']))
+        })
+        .collect()
+}
+
+fn element_links(element: ElementRef<'_>) -> Vec<(&str, LinkKind)> {
+    let value = element.value();
+    let attr = |name| value.attr(name).into_iter();
+    let mut links: Vec<(&str, LinkKind)> = match value.name() {
+        "a" => attr("href").map(|href| (href, LinkKind::Anchor)).collect(),
+        "area" => attr("href").map(|href| (href, LinkKind::Area)).collect(),
+        "iframe" | "frame" => attr("src").map(|src| (src, LinkKind::Frame)).collect(),
+        "form" => attr("action")
+            .map(|action| (action, LinkKind::Form))
+            .collect(),
+        "meta" => attr("http-equiv")
+            .filter(|equiv| equiv.eq_ignore_ascii_case("refresh"))
+            .filter_map(|_| value.attr("content").and_then(refresh_url))
+            .map(|url| (url, LinkKind::Refresh))
+            .collect(),
+        "link" => attr("href").map(|href| (href, LinkKind::Link)).collect(),
+        "script" => attr("src").map(|src| (src, LinkKind::Script)).collect(),
+        "img" | "source" => {
+            let kind = match value.name() {
+                "img" => LinkKind::Image,
+                _ => LinkKind::Source,
+            };
+            attr("src")
+                .chain(attr("srcset").flat_map(srcset_urls))
+                .map(|url| (url, kind))
+                .collect()
+        }
+        _ => vec![],
+    };
+    if let Some(style) = value.attr("style") {
+        links.extend(
+            css_urls(style)
+                .into_iter()
+                .map(|url| (url, LinkKind::Style)),
+        );
+    }
+    links
+}
+
+/// Every link on the page, in document order, resolved against `base`.
+pub fn extract_links(html: &Html, base: &Url) -> Vec<Link> {
+    let mut links = vec![];
+    for element in html.select(&Selector::parse(SELECTOR).unwrap()) {
+        let css: String = match element.value().name() {
+            "style" => element.text().collect(),
+            _ => String::new(),
+        };
+        let mut found = element_links(element);
+        if !css.is_empty() {
+            found.extend(css_urls(&css).into_iter().map(|url| (url, LinkKind::Style)));
+        }
+        let nofollow = element.attr("rel").is_some_and(|rels| {
+            rels.split_ascii_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("nofollow"))
+        });
+        links.extend(found.into_iter().filter_map(|(link, kind)| {
+            format_link_as_url(link, base).map(|url| Link {
+                url,
+                kind,
+                nofollow,
+            })
+        }));
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn link(url: &str, kind: LinkKind) -> Link {
+        Link {
+            url: to_url(url),
+            kind,
+            nofollow: false,
+        }
+    }
+
+    #[test]
+    fn extracts_every_kind_of_link() {
+        let html = Html::parse_document(
+            r#"
+<html>
+  <head>
+    <meta http-equiv="Refresh" content="5; URL='/next'">
+    <link rel="stylesheet" href="/main.css">
+    <style>body { background: url("/bg.png"); }</style>
+    <script src="/app.js"></script>
+  </head>
+  <body>
+    <a href="/one">one</a>
+    <a href="/sponsor" rel="Sponsored NOFOLLOW">sponsor</a>
+    <map><area href="/two"></map>
+    <iframe src="/embed"></iframe>
+    <form action="/search"></form>
+    <img src="/small.jpg" srcset="/medium.jpg 2x, /large.jpg 3x">
+    <video><source src="/clip.mp4"></video>
+    <div style="background-image: url(/banner.png)"></div>
+  </body>
+</html>"#,
+        );
+        assert_eq!(
+            extract_links(&html, &to_url("http://example.com/")),
+            vec![
+                link("http://example.com/next", LinkKind::Refresh),
+                link("http://example.com/main.css", LinkKind::Link),
+                link("http://example.com/bg.png", LinkKind::Style),
+                link("http://example.com/app.js", LinkKind::Script),
+                link("http://example.com/one", LinkKind::Anchor),
+                Link {
+                    nofollow: true,
+                    ..link("http://example.com/sponsor", LinkKind::Anchor)
+                },
+                link("http://example.com/two", LinkKind::Area),
+                link("http://example.com/embed", LinkKind::Frame),
+                link("http://example.com/search", LinkKind::Form),
+                link("http://example.com/small.jpg", LinkKind::Image),
+                link("http://example.com/medium.jpg", LinkKind::Image),
+                link("http://example.com/large.jpg", LinkKind::Image),
+                link("http://example.com/clip.mp4", LinkKind::Source),
+                link("http://example.com/banner.png", LinkKind::Style),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_refresh_content() {
+        assert_eq!(refresh_url("0;url=/a"), Some("/a"));
+        assert_eq!(refresh_url("5; URL=\"/b\""), Some("/b"));
+        assert_eq!(refresh_url("3; /c"), Some("/c"));
+        assert_eq!(refresh_url("30"), None);
+    }
+
+    #[test]
+    fn applies_policy_by_kind() {
+        let policy = LinkPolicy {
+            check: vec![LinkKind::Image],
+            ..LinkPolicy::default()
+        };
+        assert_eq!(policy.action(LinkKind::Anchor), LinkAction::Follow);
+        assert_eq!(policy.action(LinkKind::Refresh), LinkAction::Follow);
+        assert_eq!(policy.action(LinkKind::Form), LinkAction::Ignore);
+        assert_eq!(policy.action(LinkKind::Image), LinkAction::Check);
+        assert_eq!(policy.action(LinkKind::Script), LinkAction::Ignore);
+    }
+}
@@ -0,0 +1,169 @@
+use scraper::{ElementRef, Html, Selector};
+use url::Url;
+
+use super::{link_gatherer::format_link_as_url, URLContentGetterError};
+
+/// How to log in to the site with an HTML form, keeping the session cookie it sets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Login {
+    /// The page with the login form
+    pub url: Url,
+    /// CSS selector of the login form e.g. `form#login`
+    pub form_selector: String,
+    /// Values of the form's fields, replacing any the form has. Other fields e.g. CSRF tokens
+    /// are submitted with the form's values
+    pub fields: Vec<(String, String)>,
+}
+
+impl Login {
+    /// Whether a redirect to `location` is to the login page, meaning the session has expired.
+    pub fn is_login_page(&self, location: &Url) -> bool {
+        location.origin() == self.url.origin() && location.path() == self.url.path()
+    }
+}
+
+fn content_error(message: String) -> URLContentGetterError {
+    tracing::error!("{}", message);
+    URLContentGetterError::Content(message)
+}
+
+// the value a field submits, if any
+fn field_value(field: ElementRef<'_>) -> Option<String> {
+    let value = field.value();
+    match value.name() {
+        "textarea" => Some(field.text().collect()),
+        "select" => field
+            .select(&Selector::parse("option[selected]").unwrap())
+            .chain(field.select(&Selector::parse("option").unwrap()))
+            .next()
+            .map(|option| {
+                option
+                    .attr("value")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| option.text().collect::<String>().trim().to_string())
+            }),
+        _ => match value
+            .attr("type")
+            .unwrap_or("text")
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "submit" | "button" | "image" | "reset" | "file" => None,
+            "checkbox" | "radio" if value.attr("checked").is_none() => None,
+            "checkbox" | "radio" => Some(value.attr("value").unwrap_or("on").to_string()),
+            _ => Some(value.attr("value").unwrap_or_default().to_string()),
+        },
+    }
+}
+
+/// The URL the login form on the page submits to and the fields it submits, with the login's
+/// values filled in.
+pub(super) fn fill_form(
+    body: &str,
+    url: &Url,
+    login: &Login,
+) -> Result<(Url, Vec<(String, String)>), URLContentGetterError> {
+    let html = Html::parse_document(body);
+    let selector = Selector::parse(&login.form_selector)
+        .map_err(|_| content_error(format!("invalid form selector {}", login.form_selector)))?;
+    let form = html
+        .select(&selector)
+        .next()
+        .ok_or_else(|| content_error(format!("no form matching {}", login.form_selector)))?;
+    let action = form
+        .attr("action")
+        .filter(|action| !action.trim().is_empty())
+        .and_then(|action| format_link_as_url(action, url))
+        .unwrap_or_else(|| url.clone());
+
+    let mut fields: Vec<(String, String)> = form
+        .select(&Selector::parse("input[name], textarea[name], select[name]").unwrap())
+        .filter(|field| {
+            !login
+                .fields
+                .iter()
+                .any(|(name, _)| field.attr("name") == Some(name))
+        })
+        .filter_map(|field| Some((field.attr("name")?.to_string(), field_value(field)?)))
+        .collect();
+    fields.extend(login.fields.iter().cloned());
+    Ok((action, fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn login(form_selector: &str) -> Login {
+        Login {
+            url: to_url("https://example.com/login"),
+            form_selector: form_selector.to_string(),
+            fields: fields(&[("username", "admin"), ("password", "secret")]),
+        }
+    }
+
+    #[test]
+    fn fills_in_login_form() {
+        let html = r#"
+<form id="search" action="/search"><input name="q"></form>
+<form id="login" action="/session" method="post">
+  <input type="hidden" name="csrf" value="t0k3n">
+  <input name="username" value="guest">
+  <input type="password" name="password">
+  <input type="checkbox" name="remember" checked>
+  <input type="checkbox" name="newsletter" value="yes">
+  <select name="lang"><option value="en">English</option><option value="fr" selected>French</option></select>
+  <input type="submit" name="go" value="Log in">
+</form>"#;
+
+        assert_eq!(
+            fill_form(html, &to_url("https://example.com/login"), &login("#login")),
+            Ok((
+                to_url("https://example.com/session"),
+                fields(&[
+                    ("csrf", "t0k3n"),
+                    ("remember", "on"),
+                    ("lang", "fr"),
+                    ("username", "admin"),
+                    ("password", "secret"),
+                ])
+            ))
+        );
+    }
+
+    #[test]
+    fn submits_to_the_page_without_an_action() {
+        let url = to_url("https://example.com/login?next=/");
+        assert_eq!(
+            fill_form("<form></form>", &url, &login("form")).map(|(action, _)| action),
+            Ok(url)
+        );
+        assert_eq!(
+            fill_form(
+                "<form></form>",
+                &to_url("https://example.com/login"),
+                &login("#login")
+            ),
+            Err(URLContentGetterError::Content(
+                "no form matching #login".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn recognises_login_page() {
+        let login = login("form");
+        assert!(login.is_login_page(&to_url("https://example.com/login?next=/private")));
+        assert!(!login.is_login_page(&to_url("https://example.com/logout")));
+        assert!(!login.is_login_page(&to_url("http://example.com/login")));
+    }
+}
@@ -1,4 +1,22 @@
+mod client_config;
+mod credentials;
+mod extractors;
+mod host_rewrite;
+#[allow(clippy::module_inception)]
 mod link_gatherer;
+mod links;
+mod login;
 mod url_content_getter;
-pub use link_gatherer::{LinkGatherer, Page};
-pub use url_content_getter::{URLContentGetter, URLContentGetterError};
+pub use client_config::{ClientConfig, Compression, RedirectPolicy, Resolve, USER_AGENT};
+pub use credentials::{parse_header, Credentials};
+pub use extractors::{
+    CssExtractor, ExtractorRegistry, FeedExtractor, JsonExtractor, SitemapExtractor,
+    UriListExtractor,
+};
+pub use host_rewrite::HostRewrite;
+pub use link_gatherer::{Alternate, LinkGatherer, Page, PageRecord};
+pub use links::{Link, LinkAction, LinkKind, LinkPolicy};
+pub use login::Login;
+pub use url_content_getter::{
+    media_type, HttpClient, RedirectHop, URLContentGetter, URLContentGetterError,
+};
@@ -1,47 +1,1033 @@
-use std::future::Future;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
 
+use encoding_rs::{Encoding, UTF_8};
+use jiff::{fmt::rfc2822, Timestamp};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use url::Url;
 
-#[derive(Error, Debug, Clone, PartialEq)]
+use super::{
+    client_config::{with_port, USER_AGENT},
+    credentials::Credentials,
+    host_rewrite::HostRewrite,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum URLContentGetterError {
     #[error("request error")]
     Request(u16),
     #[error("content error")]
     Content(String),
+    #[error("timed out")]
+    Timeout,
+}
+
+impl URLContentGetterError {
+    /// Whether the request may succeed if tried again i.e. it failed to connect, timed out or
+    /// the server errored. Client errors and unusable content fail the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            URLContentGetterError::Request(status) => *status == 0 || *status >= 500,
+            URLContentGetterError::Timeout => true,
+            URLContentGetterError::Content(_) => false,
+        }
+    }
+}
+
+/// Most redirects followed when fetching content whose redirects aren't recorded e.g. robots.txt
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedirectHop {
+    pub status: u16,
+    pub location: Url,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Response {
+    pub body: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// As given by the Content-Length header
+    pub content_length: Option<u64>,
+    /// Time from sending the request until the body was read
+    pub response_time: Duration,
+    /// The URL the body came from, which differs from the one requested if the client followed redirects
+    pub final_url: Option<Url>,
+    pub fetched_at: Timestamp,
+    pub last_modified: Option<Timestamp>,
+    pub etag: Option<String>,
+    /// The value of each X-Robots-Tag header
+    pub robots_tag: Vec<String>,
+    /// Set instead of the body when the URL redirects elsewhere
+    pub redirect: Option<RedirectHop>,
+    /// The redirects the client followed to reach the response, in order
+    pub redirects: Vec<RedirectHop>,
+}
+
+/// Which responses have their body read, so that resources which can't contain links aren't
+/// downloaded.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BodyPolicy {
+    /// Content types, without parameters, whose body is read. Bodies of any other type are left
+    /// unread, other than when the response has no Content-Type. `None` reads every body.
+    pub content_types: Option<Vec<String>>,
+    /// Bodies larger than this many bytes are abandoned with an error
+    pub max_size: Option<u64>,
+}
+
+/// Validators from an earlier response for the same URL, sent so that the server can reply
+/// 304 Not Modified rather than sending the content again.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Conditions {
+    pub etag: Option<String>,
+    pub last_modified: Option<Timestamp>,
+}
+
+const UNCONDITIONAL: Conditions = Conditions {
+    etag: None,
+    last_modified: None,
+};
+
+const READ_ALL: BodyPolicy = BodyPolicy {
+    content_types: None,
+    max_size: None,
+};
+
+/// The media type of a Content-Type without its parameters e.g. `text/html` for
+/// `text/html; charset=utf-8`.
+pub fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+impl BodyPolicy {
+    pub fn reads(&self, content_type: Option<&str>) -> bool {
+        match (&self.content_types, content_type) {
+            (Some(content_types), Some(content_type)) => {
+                content_types.contains(&media_type(content_type))
+            }
+            _ => true,
+        }
+    }
+}
+
+/// A client which adds credentials to requests for the crawl's hosts, and fetches URLs on
+/// rewritten origins from their targets.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    pub client: reqwest::Client,
+    pub credentials: Credentials,
+    pub rewrites: Vec<HostRewrite>,
+    /// Most redirects followed to fetch a page, which are otherwise returned. Each hop is sent
+    /// separately so it only gets the credentials for its own host.
+    pub follow_redirects: Option<usize>,
+}
+
+/// A client following redirects, without timeouts or credentials.
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient {
+            follow_redirects: Some(MAX_REDIRECTS),
+            ..HttpClient::from(
+                reqwest::Client::builder()
+                    .user_agent(USER_AGENT)
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .unwrap(),
+            )
+        }
+    }
+}
+
+impl From<reqwest::Client> for HttpClient {
+    fn from(client: reqwest::Client) -> Self {
+        HttpClient {
+            client,
+            credentials: Credentials::default(),
+            rewrites: vec![],
+            follow_redirects: None,
+        }
+    }
+}
+
+impl HttpClient {
+    /// The URL fetched for `url`.
+    fn target(&self, url: &Url) -> Url {
+        self.rewrites
+            .iter()
+            .find_map(|rewrite| rewrite.target(url))
+            .unwrap_or_else(|| url.clone())
+    }
+
+    /// The URL reported for one fetched.
+    fn canonical(&self, url: &Url) -> Url {
+        self.rewrites
+            .iter()
+            .find_map(|rewrite| rewrite.canonical(url))
+            .unwrap_or_else(|| url.clone())
+    }
+}
+
+impl From<String> for Response {
+    fn from(body: String) -> Self {
+        Response {
+            body,
+            ..Response::default()
+        }
+    }
 }
 
 pub trait URLContentGetter {
+    /// The response, with its body left empty when the policy doesn't read it or the content
+    /// hasn't changed since the conditions were recorded, in which case its status is 304.
+    fn get_http_response(
+        &self,
+        url: &Url,
+        body: &BodyPolicy,
+        conditions: &Conditions,
+    ) -> impl Future<Output = Result<Response, URLContentGetterError>> + Send;
+
     fn get_http_response_body(
         &self,
-        url: &str,
-    ) -> impl Future<Output = Result<String, URLContentGetterError>> + Send;
+        url: &Url,
+    ) -> impl Future<Output = Result<String, URLContentGetterError>> + Send {
+        let response = self.get_http_response(url, &READ_ALL, &UNCONDITIONAL);
+        async move { response.await.map(|response| response.body) }
+    }
+
+    /// The raw response body, for content which may not be text e.g. gzipped files.
+    fn get_http_response_bytes(
+        &self,
+        url: &Url,
+    ) -> impl Future<Output = Result<Vec<u8>, URLContentGetterError>> + Send {
+        let body = self.get_http_response_body(url);
+        async move { body.await.map(String::into_bytes) }
+    }
+
+    /// Checks a URL can be fetched without reading its content, returning the response status.
+    fn get_http_status(
+        &self,
+        url: &Url,
+    ) -> impl Future<Output = Result<u16, URLContentGetterError>> + Send {
+        let body = self.get_http_response_body(url);
+        async move { body.await.map(|_| 200) }
+    }
+
+    /// Submits the fields as a URL encoded form, returning the response status.
+    fn post_form(
+        &self,
+        url: &Url,
+        fields: &[(String, String)],
+    ) -> impl Future<Output = Result<u16, URLContentGetterError>> + Send;
+}
+
+fn last_modified(headers: &reqwest::header::HeaderMap) -> Option<Timestamp> {
+    let value = headers.get(reqwest::header::LAST_MODIFIED)?.to_str().ok()?;
+    match rfc2822::parse(value) {
+        Ok(zoned) => Some(zoned.timestamp()),
+        Err(err) => {
+            tracing::debug!("Unable to parse Last-Modified {:?} - {}", value, err);
+            None
+        }
+    }
+}
+
+// the redirect with its Location on the canonical origin
+fn redirect_hop(client: &HttpClient, url: &Url, resp: &reqwest::Response) -> Option<RedirectHop> {
+    if !resp.status().is_redirection() {
+        return None;
+    }
+    let location = resp
+        .headers()
+        .get(reqwest::header::LOCATION)?
+        .to_str()
+        .ok()?;
+    Some(RedirectHop {
+        status: resp.status().as_u16(),
+        location: client.canonical(&url.join(location).ok()?),
+    })
+}
+
+fn http_date(timestamp: &Timestamp) -> Option<reqwest::header::HeaderValue> {
+    rfc2822::DateTimePrinter::new()
+        .timestamp_to_rfc9110_string(timestamp)
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Sends the request with any credentials, returning successful responses, redirects with a
+/// Location and 304 Not Modified.
+async fn dispatch(
+    client: &HttpClient,
+    url: &Url,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, URLContentGetterError> {
+    let request = client.credentials.authorise(url, request);
+    match with_port(&client.target(url), request.send()).await {
+        Ok(resp) => {
+            let status = resp.status();
+            if !status.is_success()
+                && status != reqwest::StatusCode::NOT_MODIFIED
+                && redirect_hop(client, url, &resp).is_none()
+            {
+                tracing::error!("unsuccessful response status {}", status);
+                return Err(URLContentGetterError::Request(status.as_u16()));
+            }
+            Ok(resp)
+        }
+        Err(err) if err.is_timeout() => Err(timeout(err)),
+        Err(err) => {
+            tracing::error!("{}", err.to_string());
+            Err(URLContentGetterError::Request(
+                err.status().map(|sc| sc.as_u16()).unwrap_or(0),
+            ))
+        }
+    }
+}
+
+/// Sends the request, adding the conditions' validators.
+async fn send(
+    client: &HttpClient,
+    method: reqwest::Method,
+    url: &Url,
+    conditions: &Conditions,
+) -> Result<reqwest::Response, URLContentGetterError> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(etag) = conditions.etag.as_ref().and_then(|etag| etag.parse().ok()) {
+        headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(date) = conditions.last_modified.as_ref().and_then(http_date) {
+        headers.insert(reqwest::header::IF_MODIFIED_SINCE, date);
+    }
+    let request = client
+        .client
+        .request(method, client.target(url))
+        .headers(headers);
+    dispatch(client, url, request).await
+}
+
+/// Whether to follow a redirect after those already followed, failing once there are more than
+/// the client follows.
+fn follows(
+    client: &HttpClient,
+    followed: usize,
+    redirect: &RedirectHop,
+) -> Result<bool, URLContentGetterError> {
+    match client.follow_redirects {
+        Some(max) if followed < max => Ok(true),
+        Some(max) => {
+            tracing::error!("more than {} redirects", max);
+            Err(URLContentGetterError::Request(redirect.status))
+        }
+        None => Ok(false),
+    }
+}
+
+/// Sends the request, following redirects as the client does for pages, returning any redirect
+/// it doesn't follow.
+async fn send_following(
+    client: &HttpClient,
+    method: reqwest::Method,
+    url: &Url,
+) -> Result<reqwest::Response, URLContentGetterError> {
+    let mut url = url.clone();
+    let mut followed = 0;
+    loop {
+        let resp = send(client, method.clone(), &url, &UNCONDITIONAL).await?;
+        match redirect_hop(client, &url, &resp) {
+            Some(hop) if follows(client, followed, &hop)? => {
+                followed += 1;
+                url = hop.location;
+            }
+            _ => return Ok(resp),
+        }
+    }
+}
+
+/// The response to read content from, which a redirect that wasn't followed doesn't have.
+fn content(resp: reqwest::Response) -> Result<reqwest::Response, URLContentGetterError> {
+    match resp.status().is_redirection() {
+        true => Err(URLContentGetterError::Request(resp.status().as_u16())),
+        false => Ok(resp),
+    }
+}
+
+fn timeout(err: reqwest::Error) -> URLContentGetterError {
+    tracing::error!("{}", err.to_string());
+    URLContentGetterError::Timeout
+}
+
+fn content_error(err: reqwest::Error) -> URLContentGetterError {
+    if err.is_timeout() {
+        return timeout(err);
+    }
+    tracing::error!("{}", err.to_string());
+    URLContentGetterError::Content(err.to_string())
+}
+
+fn too_large(max_size: u64) -> URLContentGetterError {
+    tracing::error!("body larger than {} bytes", max_size);
+    URLContentGetterError::Content(format!("body larger than {} bytes", max_size))
+}
+
+/// Reads the body a chunk at a time, abandoning it once it's larger than `max_size`.
+async fn read_body(
+    mut resp: reqwest::Response,
+    max_size: Option<u64>,
+) -> Result<Vec<u8>, URLContentGetterError> {
+    let max_size = max_size.unwrap_or(u64::MAX);
+    if resp
+        .content_length()
+        .is_some_and(|length| length > max_size)
+    {
+        return Err(too_large(max_size));
+    }
+    let mut body = vec![];
+    while let Some(chunk) = resp.chunk().await.map_err(content_error)? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > max_size {
+            return Err(too_large(max_size));
+        }
+    }
+    Ok(body)
+}
+
+/// Decodes the body using the charset from the Content-Type, defaulting to UTF-8.
+fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .into_iter()
+        .flat_map(|content_type| content_type.split(';').skip(1))
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, label)| Encoding::for_label(label.trim().trim_matches('"').as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode(body).0.into_owned()
 }
 
-impl URLContentGetter for reqwest::Client {
+/// Redirects are returned rather than followed when the client is built with
+/// `redirect::Policy::none()` and doesn't follow them itself, in which case fetching only the
+/// content fails with the redirect's status.
+impl URLContentGetter for HttpClient {
     #[tracing::instrument(skip(self))]
-    fn get_http_response_body(
+    async fn get_http_response(
         &self,
-        url: &str,
-    ) -> impl Future<Output = Result<String, URLContentGetterError>> + Send {
-        async move {
-            let url = url.to_string();
-            let mut headers = reqwest::header::HeaderMap::new();
-            headers.insert("user-agent", "scrapey/1.0".parse().unwrap());
-            match self.get(url).headers(headers).send().await {
-                Ok(resp) => match resp.text().await {
-                    Ok(content) => Ok(content),
-                    Err(err) => {
-                        tracing::error!("{}", err.to_string());
-                        Err(URLContentGetterError::Content(err.to_string()))
-                    }
-                },
-                Err(err) => {
-                    tracing::error!("{}", err.to_string());
-                    Err(URLContentGetterError::Request(
-                        err.status().and_then(|sc| Some(sc.as_u16())).unwrap_or(0),
-                    ))
-                }
+        url: &Url,
+        body: &BodyPolicy,
+        conditions: &Conditions,
+    ) -> Result<Response, URLContentGetterError> {
+        let fetched_at = Timestamp::now();
+        let start = Instant::now();
+        let mut redirects: Vec<RedirectHop> = vec![];
+        let resp = loop {
+            let hop_url = redirects.last().map_or(url, |hop| &hop.location);
+            // the validators are only for the URL requested
+            let conditions = match redirects.is_empty() {
+                true => conditions,
+                false => &UNCONDITIONAL,
+            };
+            let resp = send(self, reqwest::Method::GET, hop_url, conditions).await?;
+            let Some(redirect) = redirect_hop(self, hop_url, &resp) else {
+                break resp;
+            };
+            tracing::info!("redirected {} to {}", redirect.status, redirect.location);
+            if !follows(self, redirects.len(), &redirect)? {
+                return Ok(Response {
+                    redirect: Some(redirect),
+                    ..Response::default()
+                });
             }
+            redirects.push(redirect);
+        };
+        let status = resp.status().as_u16();
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            tracing::info!("Not modified");
+            return Ok(Response {
+                status,
+                response_time: start.elapsed(),
+                fetched_at,
+                ..Response::default()
+            });
         }
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let content_length = resp.content_length();
+        let final_url = Some(self.canonical(resp.url()));
+        let last_modified = last_modified(resp.headers());
+        let etag = resp
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let robots_tag = resp
+            .headers()
+            .get_all("x-robots-tag")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(str::to_string)
+            .collect();
+        let body = match body.reads(content_type.as_deref()) {
+            true => decode(
+                &read_body(resp, body.max_size).await?,
+                content_type.as_deref(),
+            ),
+            false => {
+                tracing::info!("Not reading {:?} body", content_type);
+                String::new()
+            }
+        };
+        Ok(Response {
+            body,
+            status,
+            content_type,
+            content_length,
+            response_time: start.elapsed(),
+            final_url,
+            fetched_at,
+            last_modified,
+            etag,
+            robots_tag,
+            redirect: None,
+            redirects,
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_http_response_body(&self, url: &Url) -> Result<String, URLContentGetterError> {
+        let resp = content(send_following(self, reqwest::Method::GET, url).await?)?;
+        resp.text().await.map_err(content_error)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_http_response_bytes(&self, url: &Url) -> Result<Vec<u8>, URLContentGetterError> {
+        let resp = content(send_following(self, reqwest::Method::GET, url).await?)?;
+        let body = resp.bytes().await.map_err(content_error)?;
+        Ok(body.to_vec())
+    }
+
+    /// Sends a HEAD request, falling back to GET when the server responds that it doesn't
+    /// handle HEAD (405 or 501).
+    #[tracing::instrument(skip(self))]
+    async fn get_http_status(&self, url: &Url) -> Result<u16, URLContentGetterError> {
+        match send_following(self, reqwest::Method::HEAD, url).await {
+            Ok(resp) => Ok(resp.status().as_u16()),
+            Err(URLContentGetterError::Request(405 | 501)) => {
+                send_following(self, reqwest::Method::GET, url)
+                    .await
+                    .map(|resp| resp.status().as_u16())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    #[tracing::instrument(skip(self, fields))]
+    async fn post_form(
+        &self,
+        url: &Url,
+        fields: &[(String, String)],
+    ) -> Result<u16, URLContentGetterError> {
+        let request = self.client.post(self.target(url)).form(fields);
+        dispatch(self, url, request)
+            .await
+            .map(|resp| resp.status().as_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, headers, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use std::sync::Arc;
+
+    use crate::link_gatherer::ClientConfig;
+
+    use super::*;
+
+    async fn stub_server(route: &str, response: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(route))
+            .and(header("user-agent", "scrapey/1.0"))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_body_on_success() {
+        let server = stub_server(
+            "/page",
+            ResponseTemplate::new(200).set_body_string("<a href=\"/one\">one</a>"),
+        )
+        .await;
+
+        let body = HttpClient::default()
+            .get_http_response_body(&Url::parse(&format!("{}/page", server.uri())).unwrap())
+            .await;
+        assert_eq!(body, Ok("<a href=\"/one\">one</a>".to_string()));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_captures_response_metadata() {
+        let server = stub_server(
+            "/page",
+            ResponseTemplate::new(200)
+                .insert_header("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+                .append_header("x-robots-tag", "noindex")
+                .append_header("x-robots-tag", "otherbot: nofollow")
+                .set_body_raw("content", "text/html; charset=utf-8"),
+        )
+        .await;
+        let url = Url::parse(&format!("{}/page", server.uri())).unwrap();
+
+        let before = Timestamp::now();
+        let response = HttpClient::default()
+            .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.body, "content");
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.content_type.as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(response.content_length, Some(7));
+        assert_eq!(response.final_url, Some(url));
+        assert!(response.fetched_at >= before);
+        assert_eq!(
+            response.last_modified,
+            Some("2015-10-21T07:28:00Z".parse().unwrap())
+        );
+        assert_eq!(response.robots_tag, vec!["noindex", "otherbot: nofollow"]);
+        assert_eq!(response.redirect, None);
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_only_sends_credentials_to_the_crawls_hosts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/private"))
+            .and(header("x-api-key", "secret"))
+            .and(header("authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("private"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/private"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/private", server.uri())).unwrap();
+        // the same server under another name stands in for an external host
+        let mut external = url.clone();
+        external.set_host(Some("localhost")).unwrap();
+        let client = HttpClient {
+            credentials: Credentials {
+                hosts: vec![url.host_str().unwrap().to_string()],
+                headers: [("x-api-key".parse().unwrap(), "secret".parse().unwrap())]
+                    .into_iter()
+                    .collect(),
+                bearer_token: Some("token".to_string()),
+                ..Credentials::default()
+            },
+            ..HttpClient::default()
+        };
+
+        assert_eq!(
+            client.get_http_response_body(&url).await,
+            Ok("private".to_string())
+        );
+        assert_eq!(
+            client.get_http_response_body(&external).await,
+            Err(URLContentGetterError::Request(401))
+        );
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_sends_conditional_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/page"))
+            .and(header("if-none-match", "\"v1\""))
+            // values are split on commas when matched
+            .and(headers(
+                "if-modified-since",
+                vec!["Wed", "21 Oct 2015 07:28:00 GMT"],
+            ))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"v1\"")
+                    .set_body_raw("content", "text/html"),
+            )
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/page", server.uri())).unwrap();
+        let client = HttpClient::default();
+
+        let response = client
+            .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.etag.as_deref(), Some("\"v1\""));
+        let conditions = Conditions {
+            etag: response.etag,
+            last_modified: Some("2015-10-21T07:28:00Z".parse().unwrap()),
+        };
+        let response = client
+            .get_http_response(&url, &BodyPolicy::default(), &conditions)
+            .await
+            .unwrap();
+        assert_eq!(response.status, 304);
+        assert_eq!(response.body, "");
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_only_reads_bodies_of_given_content_types() {
+        let server = stub_server(
+            "/report.pdf",
+            ResponseTemplate::new(200).set_body_raw("%PDF-1.7", "application/pdf"),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(&b"caf\xe9"[..], "text/html; charset=ISO-8859-1"),
+            )
+            .mount(&server)
+            .await;
+        let policy = BodyPolicy {
+            content_types: Some(vec!["text/html".to_string()]),
+            max_size: None,
+        };
+        let client = HttpClient::default();
+
+        let response = client
+            .get_http_response(
+                &Url::parse(&format!("{}/report.pdf", server.uri())).unwrap(),
+                &policy,
+                &Conditions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.body, "");
+        assert_eq!(response.content_type.as_deref(), Some("application/pdf"));
+        assert_eq!(response.content_length, Some(8));
+        let response = client
+            .get_http_response(
+                &Url::parse(&format!("{}/page", server.uri())).unwrap(),
+                &policy,
+                &Conditions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.body, "café");
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_abandons_oversized_bodies() {
+        let server = stub_server(
+            "/page",
+            ResponseTemplate::new(200).set_body_raw("0123456789", "text/html"),
+        )
+        .await;
+        let url = Url::parse(&format!("{}/page", server.uri())).unwrap();
+        let client = HttpClient::default();
+        let policy = |max_size| BodyPolicy {
+            content_types: None,
+            max_size: Some(max_size),
+        };
+
+        let response = client
+            .get_http_response(&url, &policy(10), &Conditions::default())
+            .await;
+        assert_eq!(
+            response.map(|response| response.body),
+            Ok("0123456789".to_string())
+        );
+        let response = client
+            .get_http_response(&url, &policy(9), &Conditions::default())
+            .await;
+        assert_eq!(
+            response,
+            Err(URLContentGetterError::Content(
+                "body larger than 9 bytes".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn body_policy_reads_listed_and_untyped_content() {
+        let policy = BodyPolicy {
+            content_types: Some(vec!["text/html".to_string()]),
+            max_size: None,
+        };
+        assert!(policy.reads(Some("Text/HTML; charset=utf-8")));
+        assert!(policy.reads(None));
+        assert!(!policy.reads(Some("image/png")));
+        assert!(BodyPolicy::default().reads(Some("image/png")));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_raw_bytes() {
+        let bytes = vec![0x1f, 0x8b, 0x08, 0x00, 0xff];
+        let server = stub_server(
+            "/file.gz",
+            ResponseTemplate::new(200).set_body_bytes(bytes.clone()),
+        )
+        .await;
+
+        let body = HttpClient::default()
+            .get_http_response_bytes(&Url::parse(&format!("{}/file.gz", server.uri())).unwrap())
+            .await;
+        assert_eq!(body, Ok(bytes));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_checks_status_with_head() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let status = HttpClient::default()
+            .get_http_status(&Url::parse(&format!("{}/page", server.uri())).unwrap())
+            .await;
+        assert_eq!(status, Ok(204));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_falls_back_to_get_when_head_not_allowed() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(405))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(501))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = HttpClient::default();
+        let status = client
+            .get_http_status(&Url::parse(&format!("{}/page", server.uri())).unwrap())
+            .await;
+        assert_eq!(status, Ok(200));
+        let status = client
+            .get_http_status(&Url::parse(&format!("{}/missing", server.uri())).unwrap())
+            .await;
+        assert_eq!(status, Err(URLContentGetterError::Request(404)));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_head_errors_without_get() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/down"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = HttpClient::default();
+        let status = client
+            .get_http_status(&Url::parse(&format!("{}/missing", server.uri())).unwrap())
+            .await;
+        assert_eq!(status, Err(URLContentGetterError::Request(404)));
+        let status = client
+            .get_http_status(&Url::parse(&format!("{}/down", server.uri())).unwrap())
+            .await;
+        assert_eq!(status, Err(URLContentGetterError::Request(503)));
+    }
+
+    fn not_following() -> HttpClient {
+        HttpClient::from(ClientConfig::default().build(Arc::default()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_redirect() {
+        let server = stub_server(
+            "/old",
+            ResponseTemplate::new(301).insert_header("location", "/new?a=1"),
+        )
+        .await;
+        let url = Url::parse(&format!("{}/old", server.uri())).unwrap();
+
+        let response = not_following()
+            .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+            .await;
+        assert_eq!(
+            response,
+            Ok(Response {
+                redirect: Some(RedirectHop {
+                    status: 301,
+                    location: url.join("/new?a=1").unwrap(),
+                }),
+                ..Response::default()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_follows_redirects_for_content_as_for_pages() {
+        let server = stub_server(
+            "/old",
+            ResponseTemplate::new(302).insert_header("location", "/new"),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/new"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("content"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/loop"))
+            .respond_with(ResponseTemplate::new(307).insert_header("location", "/loop"))
+            .mount(&server)
+            .await;
+        let old = Url::parse(&format!("{}/old", server.uri())).unwrap();
+        let client = HttpClient {
+            follow_redirects: Some(3),
+            ..not_following()
+        };
+
+        assert_eq!(
+            client.get_http_response_body(&old).await,
+            Ok("content".to_string())
+        );
+        assert_eq!(
+            client
+                .get_http_response_body(&Url::parse(&format!("{}/loop", server.uri())).unwrap())
+                .await,
+            Err(URLContentGetterError::Request(307))
+        );
+        assert_eq!(server.received_requests().await.unwrap().len(), 6);
+
+        assert_eq!(
+            not_following().get_http_response_body(&old).await,
+            Err(URLContentGetterError::Request(302))
+        );
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_fetches_rewritten_origin() {
+        let server = stub_server("/new", ResponseTemplate::new(200).set_body_string("new")).await;
+        Mock::given(method("GET"))
+            .and(path("/old"))
+            .respond_with(
+                ResponseTemplate::new(301)
+                    .insert_header("location", format!("{}/new", server.uri())),
+            )
+            .mount(&server)
+            .await;
+        let rewrites = vec![format!("https://www.example.com={}", server.uri())
+            .parse()
+            .unwrap()];
+        let url = Url::parse("https://www.example.com/old").unwrap();
+
+        let response = HttpClient {
+            rewrites: rewrites.clone(),
+            ..not_following()
+        }
+        .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+        .await;
+        assert_eq!(
+            response.map(|response| response.redirect),
+            Ok(Some(RedirectHop {
+                status: 301,
+                location: url.join("/new").unwrap(),
+            }))
+        );
+        let response = HttpClient {
+            rewrites,
+            ..HttpClient::default()
+        }
+        .get_http_response(&url, &BodyPolicy::default(), &Conditions::default())
+        .await
+        .unwrap();
+        assert_eq!(response.body, "new");
+        assert_eq!(response.final_url, Some(url.join("/new").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_status_for_client_error() {
+        let server = stub_server(
+            "/missing",
+            ResponseTemplate::new(404).set_body_string("<a href=\"/one\">one</a>"),
+        )
+        .await;
+
+        let body = HttpClient::default()
+            .get_http_response_body(&Url::parse(&format!("{}/missing", server.uri())).unwrap())
+            .await;
+        assert_eq!(body, Err(URLContentGetterError::Request(404)));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_status_for_server_error() {
+        let server = stub_server("/broken", ResponseTemplate::new(503)).await;
+
+        let body = HttpClient::default()
+            .get_http_response_body(&Url::parse(&format!("{}/broken", server.uri())).unwrap())
+            .await;
+        assert_eq!(body, Err(URLContentGetterError::Request(503)));
+    }
+
+    #[tokio::test]
+    async fn reqwest_client_returns_zero_status_when_unreachable() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let body = HttpClient::default()
+            .get_http_response_body(&Url::parse(&format!("http://{}/gone", addr)).unwrap())
+            .await;
+        assert_eq!(body, Err(URLContentGetterError::Request(0)));
     }
 }
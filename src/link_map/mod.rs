@@ -1,39 +1,172 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    io::Write,
+};
 
-use crate::link_gatherer::URLContentGetterError;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::link_gatherer::{PageRecord, URLContentGetterError};
 
 mod transform;
 
-pub use transform::to_tree;
+pub use transform::{
+    to_broken_links_report, to_coverage_report, to_dot, to_json, to_mermaid, to_recrawl_summary,
+    to_sitemaps, to_tree, SitemapFile, MAX_BYTES, MAX_URLS,
+};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum SkipReason {
+    Robots,
+    Limit,
+    /// Only linked to with `rel="nofollow"` or from pages asking for their links not to be followed
+    Nofollow,
+}
 
-#[derive(Debug, PartialEq, Clone)]
+// most values are pages so boxing the record would only add an allocation per page
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum LinkMapValue {
-    Links(Vec<String>),
+    /// A page which was fetched successfully
+    Page(PageRecord),
     Error(URLContentGetterError),
+    Skipped(SkipReason),
+    /// A link outside the site which was checked but not followed, with its response status
+    External(Result<u16, URLContentGetterError>),
+    /// A page which redirected elsewhere, with the redirect status and where it redirected to
+    Redirect(u16, Url),
+}
+
+impl LinkMapValue {
+    /// The URLs this page leads to, either its links or where it redirected to.
+    pub fn links(&self) -> Vec<&Url> {
+        match self {
+            LinkMapValue::Page(page) => page.urls().collect(),
+            LinkMapValue::Redirect(_, location) => vec![location],
+            _ => vec![],
+        }
+    }
+}
+
+/// A problem with the chain of redirects starting at a URL.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RedirectIssue {
+    /// The redirects lead back to a URL earlier in the chain
+    Loop,
+    /// More redirects than allowed are followed before reaching a page
+    Long(usize),
+}
+
+impl Display for RedirectIssue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RedirectIssue::Loop => write!(f, "redirect loop"),
+            RedirectIssue::Long(hops) => write!(f, "{} redirects", hops),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct LinkMap {
-    pub root: String,
-    pub map: HashMap<String, LinkMapValue>,
+    pub root: Url,
+    pub map: HashMap<Url, LinkMapValue>,
+    /// URLs listed in the site's sitemaps
+    pub sitemap: HashSet<Url>,
 }
 
 impl LinkMap {
-    pub fn new(root: String) -> Self {
+    pub fn new(root: Url) -> Self {
         LinkMap {
             root,
             map: HashMap::new(),
+            sitemap: HashSet::new(),
         }
     }
 
-    pub fn add(&mut self, url: String, value: LinkMapValue) {
+    pub fn add(&mut self, url: Url, value: LinkMapValue) {
         self.map.insert(url, value);
     }
 
-    pub fn to_tree(&self) -> String {
-        match to_tree(&self) {
-            Ok(tree) => tree,
-            _ => String::new(),
+    // the number of redirects followed from `url` before reaching a page which doesn't
+    // redirect, and whether they loop back on themselves
+    fn redirect_chain(&self, url: &Url) -> (usize, bool) {
+        let mut seen = HashSet::new();
+        let mut current = url;
+        while let Some(LinkMapValue::Redirect(_, location)) = self.map.get(current) {
+            if !seen.insert(current) {
+                return (seen.len(), true);
+            }
+            current = location;
         }
+        (seen.len(), false)
+    }
+
+    /// Flags every redirect whose chain loops or has more than `max_chain` redirects.
+    pub fn redirect_issues(&self, max_chain: usize) -> HashMap<Url, RedirectIssue> {
+        self.map
+            .iter()
+            .filter(|(_, value)| matches!(value, LinkMapValue::Redirect(..)))
+            .filter_map(|(url, _)| match self.redirect_chain(url) {
+                (_, true) => Some((url.clone(), RedirectIssue::Loop)),
+                (hops, _) if hops > max_chain => Some((url.clone(), RedirectIssue::Long(hops))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Tree of pages, flagging redirect chains longer than `max_redirect_chain`.
+    pub fn to_tree(&self, max_redirect_chain: usize) -> String {
+        to_tree(self, &self.redirect_issues(max_redirect_chain)).unwrap_or_default()
+    }
+
+    /// Graphviz DOT of the link graph, optionally collapsing pages into one node per directory.
+    pub fn to_dot(&self, cluster_dirs: bool, max_redirect_chain: usize) -> String {
+        to_dot(
+            self,
+            cluster_dirs,
+            &self.redirect_issues(max_redirect_chain),
+        )
+        .unwrap_or_default()
+    }
+
+    /// Mermaid flowchart of the link graph, optionally collapsing pages into one node per directory.
+    pub fn to_mermaid(&self, cluster_dirs: bool, max_redirect_chain: usize) -> String {
+        to_mermaid(
+            self,
+            cluster_dirs,
+            &self.redirect_issues(max_redirect_chain),
+        )
+        .unwrap_or_default()
+    }
+
+    /// Sitemap files of every fetched page, with any split files located relative to `base`.
+    pub fn to_sitemaps(&self, base: &Url) -> Vec<SitemapFile> {
+        to_sitemaps(self, base, MAX_URLS, MAX_BYTES).unwrap_or_default()
+    }
+
+    /// Lists sitemap URLs not reachable by links, reachable pages not in a sitemap and
+    /// sitemap URLs which returned errors.
+    pub fn to_coverage_report(&self) -> String {
+        to_coverage_report(self).unwrap_or_default()
+    }
+
+    /// Lists every URL which returned an error along with the pages linking to it.
+    pub fn to_broken_links_report(&self) -> String {
+        to_broken_links_report(self).unwrap_or_default()
+    }
+
+    /// Counts the pages unchanged since the previous crawl, those which changed and those it
+    /// didn't have.
+    pub fn to_recrawl_summary(&self, previous: &LinkMap) -> String {
+        to_recrawl_summary(self, previous).unwrap_or_default()
+    }
+
+    pub fn write_json(
+        &self,
+        writer: impl Write,
+        max_redirect_chain: usize,
+    ) -> serde_json::Result<()> {
+        to_json(self, &self.redirect_issues(max_redirect_chain), writer)
     }
 }
@@ -0,0 +1,103 @@
+use crate::{
+    link_gatherer::URLContentGetterError,
+    link_map::{LinkMap, LinkMapValue},
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use url::Url;
+
+/// Every URL which returned an error, inside or outside the site, with the pages linking to it.
+pub fn broken_links(link_map: &LinkMap) -> BTreeMap<&Url, (&URLContentGetterError, Vec<&Url>)> {
+    let mut broken: BTreeMap<&Url, (&URLContentGetterError, Vec<&Url>)> = link_map
+        .map
+        .iter()
+        .filter_map(|(url, value)| match value {
+            LinkMapValue::Error(err) | LinkMapValue::External(Err(err)) => {
+                Some((url, (err, vec![])))
+            }
+            _ => None,
+        })
+        .collect();
+    for (page, value) in &link_map.map {
+        for link in value.links() {
+            if let Some((_, referrers)) = broken.get_mut(link) {
+                referrers.push(page);
+            }
+        }
+    }
+    for (_, referrers) in broken.values_mut() {
+        referrers.sort();
+    }
+    broken
+}
+
+pub fn to_broken_links_report(link_map: &LinkMap) -> Result<String, std::fmt::Error> {
+    let broken = broken_links(link_map);
+    let mut output = String::new();
+
+    writeln!(output, "Broken links ({})", broken.len())?;
+    for (url, (err, referrers)) in broken {
+        match err {
+            URLContentGetterError::Request(code) => writeln!(output, "\n{} - 😵 {}", url, code)?,
+            URLContentGetterError::Content(text) => {
+                writeln!(output, "\n{} - 😵 \"{}\"", url, text)?
+            }
+            URLContentGetterError::Timeout => writeln!(output, "\n{} - 😵 ⏱️ timed out", url)?,
+        }
+        for referrer in referrers {
+            writeln!(output, "  linked from {}", referrer)?;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    #[test]
+    fn lists_broken_links_with_referring_pages() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/missing"),
+                    to_url("http://other.com/gone"),
+                    to_url("http://other.com/fine"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(vec![to_url("http://other.com/gone")].into()),
+        );
+        link_map.add(
+            to_url("http://example.com/missing"),
+            LinkMapValue::Error(URLContentGetterError::Request(404)),
+        );
+        link_map.add(
+            to_url("http://other.com/gone"),
+            LinkMapValue::External(Err(URLContentGetterError::Request(410))),
+        );
+        link_map.add(
+            to_url("http://other.com/fine"),
+            LinkMapValue::External(Ok(200)),
+        );
+
+        let expected = r#"Broken links (2)
+
+http://example.com/missing - 😵 404
+  linked from http://example.com/
+
+http://other.com/gone - 😵 410
+  linked from http://example.com/
+  linked from http://example.com/one
+"#;
+        assert_eq!(to_broken_links_report(&link_map), Ok(expected.to_string()));
+    }
+}
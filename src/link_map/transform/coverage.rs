@@ -0,0 +1,249 @@
+use crate::{
+    link_gatherer::URLContentGetterError,
+    link_map::{LinkMap, LinkMapValue},
+};
+use std::collections::{HashSet, VecDeque};
+
+use super::sitemap::is_listed;
+use std::fmt::Write;
+use url::Url;
+
+/// How the pages listed in the site's sitemaps compare to those reachable by following links.
+#[derive(Debug, PartialEq, Default)]
+pub struct Coverage {
+    /// In a sitemap but not reachable by links from the root
+    pub orphans: Vec<Url>,
+    /// Fetched by following links from the root but not in any sitemap, counting only the pages
+    /// a generated sitemap would list
+    pub missing: Vec<Url>,
+    /// In a sitemap but returned an error
+    pub errors: Vec<(Url, URLContentGetterError)>,
+}
+
+fn reachable(link_map: &LinkMap) -> HashSet<&Url> {
+    let mut reachable = HashSet::from([&link_map.root]);
+    let mut queue = VecDeque::from([&link_map.root]);
+    while let Some(url) = queue.pop_front() {
+        for link in link_map
+            .map
+            .get(url)
+            .map(LinkMapValue::links)
+            .unwrap_or_default()
+        {
+            if reachable.insert(link) {
+                queue.push_back(link);
+            }
+        }
+    }
+    reachable
+}
+
+pub fn coverage(link_map: &LinkMap) -> Coverage {
+    let reachable = reachable(link_map);
+
+    let mut orphans: Vec<Url> = link_map
+        .sitemap
+        .iter()
+        .filter(|url| !reachable.contains(url))
+        .cloned()
+        .collect();
+    orphans.sort();
+
+    let mut missing: Vec<Url> = reachable
+        .into_iter()
+        .filter(|url| {
+            matches!(link_map.map.get(*url), Some(LinkMapValue::Page(page)) if is_listed(&link_map.root, url, page))
+        })
+        .filter(|url| !link_map.sitemap.contains(*url))
+        .cloned()
+        .collect();
+    missing.sort();
+
+    let mut errors: Vec<(Url, URLContentGetterError)> = link_map
+        .sitemap
+        .iter()
+        .filter_map(|url| match link_map.map.get(url) {
+            Some(LinkMapValue::Error(err)) => Some((url.clone(), err.clone())),
+            _ => None,
+        })
+        .collect();
+    errors.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Coverage {
+        orphans,
+        missing,
+        errors,
+    }
+}
+
+pub fn to_coverage_report(link_map: &LinkMap) -> Result<String, std::fmt::Error> {
+    let Coverage {
+        orphans,
+        missing,
+        errors,
+    } = coverage(link_map);
+    let mut output = String::new();
+
+    writeln!(
+        output,
+        "Sitemap coverage - {} URLs in sitemaps",
+        link_map.sitemap.len()
+    )?;
+    writeln!(
+        output,
+        "\n🏝️ In sitemap but not reachable by links ({})",
+        orphans.len()
+    )?;
+    for url in orphans {
+        writeln!(output, "  {}", url)?;
+    }
+    writeln!(
+        output,
+        "\n🗺️ Reachable but missing from sitemap ({})",
+        missing.len()
+    )?;
+    for url in missing {
+        writeln!(output, "  {}", url)?;
+    }
+    writeln!(
+        output,
+        "\n😵 Sitemap entries with errors ({})",
+        errors.len()
+    )?;
+    for (url, err) in errors {
+        match err {
+            URLContentGetterError::Request(code) => writeln!(output, "  {} - {}", url, code)?,
+            URLContentGetterError::Content(text) => writeln!(output, "  {} - \"{}\"", url, text)?,
+            URLContentGetterError::Timeout => writeln!(output, "  {} - timed out", url)?,
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{link_gatherer::PageRecord, test_support::to_url};
+
+    use super::*;
+
+    fn link_map() -> LinkMap {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(vec![].into()),
+        );
+        link_map.add(
+            to_url("http://example.com/two"),
+            LinkMapValue::Page(vec![].into()),
+        );
+        // seeded from the sitemap, linking to a page which is otherwise unreachable
+        link_map.add(
+            to_url("http://example.com/orphan"),
+            LinkMapValue::Page(vec![to_url("http://example.com/hidden")].into()),
+        );
+        link_map.add(
+            to_url("http://example.com/hidden"),
+            LinkMapValue::Page(vec![].into()),
+        );
+        link_map.add(
+            to_url("http://example.com/gone"),
+            LinkMapValue::Error(URLContentGetterError::Request(404)),
+        );
+        link_map.sitemap = HashSet::from([
+            to_url("http://example.com/"),
+            to_url("http://example.com/one"),
+            to_url("http://example.com/orphan"),
+            to_url("http://example.com/gone"),
+        ]);
+        link_map
+    }
+
+    #[test]
+    fn compares_sitemap_to_reachable_pages() {
+        assert_eq!(
+            coverage(&link_map()),
+            Coverage {
+                orphans: vec![
+                    to_url("http://example.com/gone"),
+                    to_url("http://example.com/orphan"),
+                ],
+                missing: vec![to_url("http://example.com/two")],
+                errors: vec![(
+                    to_url("http://example.com/gone"),
+                    URLContentGetterError::Request(404)
+                )],
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_resources_missing_from_sitemap() {
+        let mut link_map = link_map();
+        link_map.add(
+            to_url("http://example.com/two"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/main.css"),
+                    to_url("http://example.com/three"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/main.css"),
+            LinkMapValue::Page(PageRecord {
+                content_type: Some("text/css".to_string()),
+                ..vec![to_url("http://example.com/bg.png")].into()
+            }),
+        );
+        link_map.add(
+            to_url("http://example.com/bg.png"),
+            LinkMapValue::Page(PageRecord {
+                content_type: Some("image/png".to_string()),
+                ..PageRecord::default()
+            }),
+        );
+        link_map.add(
+            to_url("http://example.com/three"),
+            LinkMapValue::Page(PageRecord {
+                content_type: Some("text/html; charset=utf-8".to_string()),
+                ..PageRecord::default()
+            }),
+        );
+
+        assert_eq!(
+            coverage(&link_map).missing,
+            vec![
+                to_url("http://example.com/three"),
+                to_url("http://example.com/two"),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_report() {
+        let expected = r#"Sitemap coverage - 4 URLs in sitemaps
+
+🏝️ In sitemap but not reachable by links (2)
+  http://example.com/gone
+  http://example.com/orphan
+
+🗺️ Reachable but missing from sitemap (1)
+  http://example.com/two
+
+😵 Sitemap entries with errors (1)
+  http://example.com/gone - 404
+"#;
+        assert_eq!(to_coverage_report(&link_map()), Ok(expected.to_string()));
+    }
+}
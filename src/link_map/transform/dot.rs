@@ -0,0 +1,100 @@
+use super::graph::{Graph, NodeStatus};
+use crate::link_map::{LinkMap, RedirectIssue};
+use std::{collections::HashMap, fmt::Write};
+use url::Url;
+
+fn fill_colour(status: NodeStatus) -> &'static str {
+    match status {
+        NodeStatus::Ok => "palegreen",
+        NodeStatus::Redirect => "plum",
+        NodeStatus::Error => "lightcoral",
+        NodeStatus::External => "lightgrey",
+        NodeStatus::Blocked => "khaki",
+        NodeStatus::Unvisited => "lightblue",
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Renders the link graph in Graphviz DOT format with nodes filled by status.
+pub fn to_dot(
+    link_map: &LinkMap,
+    cluster_dirs: bool,
+    redirect_issues: &HashMap<Url, RedirectIssue>,
+) -> Result<String, std::fmt::Error> {
+    let graph = Graph::new(link_map, cluster_dirs, redirect_issues);
+    let mut output = String::new();
+
+    writeln!(output, "digraph links {{")?;
+    writeln!(output, "  rankdir=LR;")?;
+    writeln!(output, "  node [shape=box, style=filled];")?;
+    for (i, node) in graph.nodes.iter().enumerate() {
+        writeln!(
+            output,
+            "  n{} [label={}, fillcolor={}, class={}{}];",
+            i,
+            quote(&node.label),
+            fill_colour(node.status),
+            node.status.name(),
+            if node.is_root { ", peripheries=2" } else { "" }
+        )?;
+    }
+    for (from, to) in graph.edges {
+        writeln!(output, "  n{} -> n{};", from, to)?;
+    }
+    writeln!(output, "}}")?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_gatherer::URLContentGetterError;
+    use crate::link_map::{LinkMapValue, SkipReason};
+    use crate::test_support::to_url;
+
+    #[test]
+    fn renders_graph() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/private"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/one"),
+            LinkMapValue::Error(URLContentGetterError::Request(500)),
+        );
+        link_map.add(
+            to_url("http://example.com/private"),
+            LinkMapValue::Skipped(SkipReason::Robots),
+        );
+
+        let expected = r#"digraph links {
+  rankdir=LR;
+  node [shape=box, style=filled];
+  n0 [label="http://example.com/", fillcolor=palegreen, class=ok, peripheries=2];
+  n1 [label="http://example.com/one", fillcolor=lightcoral, class=error];
+  n2 [label="http://example.com/private", fillcolor=khaki, class=blocked];
+  n0 -> n1;
+  n0 -> n2;
+}
+"#;
+        assert_eq!(
+            to_dot(&link_map, false, &HashMap::new()),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(quote(r#"a"b\c"#), r#""a\"b\\c""#);
+    }
+}
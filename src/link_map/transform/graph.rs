@@ -0,0 +1,257 @@
+use crate::link_map::{LinkMap, LinkMapValue, RedirectIssue, SkipReason};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use url::Url;
+
+/// Ordered so that when pages are collapsed into a directory the most notable status is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NodeStatus {
+    /// Linked to but not followed as it is outside the site
+    External,
+    Ok,
+    /// Redirected elsewhere rather than returning content
+    Redirect,
+    /// Not visited as the trace reached a limit or it was nofollow
+    Unvisited,
+    /// Not visited as robots.txt disallows it
+    Blocked,
+    Error,
+}
+
+impl NodeStatus {
+    pub const ALL: [NodeStatus; 6] = [
+        NodeStatus::Ok,
+        NodeStatus::Redirect,
+        NodeStatus::Error,
+        NodeStatus::External,
+        NodeStatus::Blocked,
+        NodeStatus::Unvisited,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeStatus::Ok => "ok",
+            NodeStatus::Redirect => "redirect",
+            NodeStatus::Error => "error",
+            NodeStatus::External => "external",
+            NodeStatus::Blocked => "blocked",
+            NodeStatus::Unvisited => "unvisited",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Node {
+    pub label: String,
+    pub status: NodeStatus,
+    pub is_root: bool,
+}
+
+/// The directed link graph of a `LinkMap` with nodes sorted by URL and edges given as node indices.
+#[derive(Debug, PartialEq)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+// the URL up to and including the last `/` of its path e.g. `http://example.com/docs/` for `http://example.com/docs/one?a=1`
+fn directory(url: &Url) -> String {
+    let mut dir = url.clone();
+    dir.set_query(None);
+    dir.set_fragment(None);
+    if let Ok(mut segments) = dir.path_segments_mut() {
+        segments.pop().push("");
+    }
+    dir.to_string()
+}
+
+fn status(value: Option<&LinkMapValue>) -> NodeStatus {
+    match value {
+        Some(LinkMapValue::Page(_)) => NodeStatus::Ok,
+        Some(LinkMapValue::Redirect(..)) => NodeStatus::Redirect,
+        Some(LinkMapValue::Error(_)) => NodeStatus::Error,
+        Some(LinkMapValue::Skipped(SkipReason::Robots)) => NodeStatus::Blocked,
+        Some(LinkMapValue::Skipped(SkipReason::Limit | SkipReason::Nofollow)) => {
+            NodeStatus::Unvisited
+        }
+        Some(LinkMapValue::External(Err(_))) => NodeStatus::Error,
+        Some(LinkMapValue::External(Ok(_))) | None => NodeStatus::External,
+    }
+}
+
+impl Graph {
+    /// Builds the graph with a node per URL or, when `cluster_dirs` is set, a node per
+    /// directory with links between pages in the same directory dropped. Redirects in
+    /// `redirect_issues` are flagged in their node's label.
+    pub fn new(
+        link_map: &LinkMap,
+        cluster_dirs: bool,
+        redirect_issues: &HashMap<Url, RedirectIssue>,
+    ) -> Self {
+        let key = |url: &Url| match cluster_dirs {
+            true => directory(url),
+            false => url.to_string(),
+        };
+
+        let mut urls: BTreeSet<&Url> = link_map.map.keys().collect();
+        urls.insert(&link_map.root);
+        for value in link_map.map.values() {
+            urls.extend(value.links());
+        }
+
+        let mut groups: BTreeMap<String, (NodeStatus, usize, Vec<RedirectIssue>)> = BTreeMap::new();
+        for url in urls {
+            let status = status(link_map.map.get(url));
+            let (group_status, count, issues) =
+                groups.entry(key(url)).or_insert((status, 0, vec![]));
+            *group_status = status.max(*group_status);
+            *count += 1;
+            issues.extend(redirect_issues.get(url));
+        }
+
+        let root = key(&link_map.root);
+        let indices: HashMap<&String, usize> =
+            groups.keys().enumerate().map(|(i, k)| (k, i)).collect();
+        let mut edges = BTreeSet::new();
+        for (url, value) in &link_map.map {
+            let from = indices[&key(url)];
+            for link in value.links() {
+                let to = indices[&key(link)];
+                if !(cluster_dirs && from == to) {
+                    edges.insert((from, to));
+                }
+            }
+        }
+
+        Graph {
+            nodes: groups
+                .iter()
+                .map(|(k, (status, count, issues))| Node {
+                    label: match (cluster_dirs, issues.as_slice()) {
+                        (true, []) => format!("{} ({})", k, count),
+                        (true, issues) => {
+                            format!("{} ({}) ⚠️ {} flagged redirects", k, count, issues.len())
+                        }
+                        (false, [issue, ..]) => format!("{} ⚠️ {}", k, issue),
+                        (false, []) => k.clone(),
+                    },
+                    status: *status,
+                    is_root: *k == root,
+                })
+                .collect(),
+            edges: edges.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_gatherer::URLContentGetterError;
+    use crate::test_support::to_url;
+
+    fn node(label: &str, status: NodeStatus, is_root: bool) -> Node {
+        Node {
+            label: label.to_string(),
+            status,
+            is_root,
+        }
+    }
+
+    fn link_map() -> LinkMap {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/docs/one"),
+                    to_url("http://example.com/docs/two"),
+                    to_url("http://other.com/"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/docs/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/docs/two"),
+                    to_url("http://example.com/"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/docs/two"),
+            LinkMapValue::Error(URLContentGetterError::Request(404)),
+        );
+        link_map
+    }
+
+    #[test]
+    fn builds_graph_of_every_url() {
+        assert_eq!(
+            Graph::new(&link_map(), false, &HashMap::new()),
+            Graph {
+                nodes: vec![
+                    node("http://example.com/", NodeStatus::Ok, true),
+                    node("http://example.com/docs/one", NodeStatus::Ok, false),
+                    node("http://example.com/docs/two", NodeStatus::Error, false),
+                    node("http://other.com/", NodeStatus::External, false),
+                ],
+                edges: vec![(0, 1), (0, 2), (0, 3), (1, 0), (1, 2)],
+            }
+        );
+    }
+
+    #[test]
+    fn clusters_by_directory() {
+        assert_eq!(
+            Graph::new(&link_map(), true, &HashMap::new()),
+            Graph {
+                nodes: vec![
+                    node("http://example.com/ (1)", NodeStatus::Ok, true),
+                    node("http://example.com/docs/ (2)", NodeStatus::Error, false),
+                    node("http://other.com/ (1)", NodeStatus::External, false),
+                ],
+                edges: vec![(0, 1), (0, 2), (1, 0)],
+            }
+        );
+    }
+
+    #[test]
+    fn flags_redirect_issues_in_labels() {
+        let mut link_map = link_map();
+        link_map.add(
+            to_url("http://example.com/docs/old"),
+            LinkMapValue::Redirect(301, to_url("http://example.com/docs/old")),
+        );
+        let issues = link_map.redirect_issues(5);
+
+        let labels: Vec<String> = Graph::new(&link_map, false, &issues)
+            .nodes
+            .into_iter()
+            .map(|node| node.label)
+            .collect();
+        assert!(labels.contains(&"http://example.com/docs/old ⚠️ redirect loop".to_string()));
+        assert_eq!(
+            Graph::new(&link_map, true, &issues).nodes[1].label,
+            "http://example.com/docs/ (3) ⚠️ 1 flagged redirects"
+        );
+    }
+
+    #[test]
+    fn directory_of_url() {
+        assert_eq!(
+            directory(&to_url("http://example.com/docs/one?a=1")),
+            "http://example.com/docs/"
+        );
+        assert_eq!(
+            directory(&to_url("http://example.com/docs/")),
+            "http://example.com/docs/"
+        );
+        assert_eq!(
+            directory(&to_url("http://example.com")),
+            "http://example.com/"
+        );
+    }
+}
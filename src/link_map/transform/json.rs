@@ -0,0 +1,418 @@
+use crate::{
+    link_gatherer::{self, URLContentGetterError},
+    link_map::{LinkMap, LinkMapValue, RedirectIssue, SkipReason},
+};
+use serde::{Serialize, Serializer};
+use std::{collections::HashMap, io::Write};
+use url::Url;
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Error<'a> {
+    Request { status_code: u16 },
+    Content { message: &'a str },
+    Timeout,
+}
+
+#[derive(Serialize)]
+struct Link<'a> {
+    url: &'a str,
+    kind: &'static str,
+    #[serde(skip_serializing_if = "is_false")]
+    nofollow: bool,
+}
+
+#[derive(Serialize)]
+struct Alternate<'a> {
+    hreflang: &'a str,
+    url: &'a str,
+}
+
+// only built while writing so the size of the largest variant doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Status<'a> {
+    Ok {
+        status_code: u16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content_type: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content_length: Option<u64>,
+        response_time_ms: u128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        final_url: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        canonical: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        robots: Option<&'a str>,
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        robots_tag: &'a [String],
+        #[serde(skip_serializing_if = "is_false")]
+        noindex: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        nofollow: bool,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        alternates: Vec<Alternate<'a>>,
+        crawled_at: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_modified: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        etag: Option<&'a str>,
+        #[serde(skip_serializing_if = "is_false")]
+        unchanged: bool,
+        links: Vec<Link<'a>>,
+    },
+    Error {
+        error: Error<'a>,
+    },
+    Skipped {
+        reason: &'static str,
+    },
+    External {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        status_code: Option<u16>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Error<'a>>,
+    },
+    Redirect {
+        status_code: u16,
+        location: &'a str,
+        /// Whether the redirects lead back to a URL earlier in the chain
+        #[serde(skip_serializing_if = "is_false")]
+        redirect_loop: bool,
+        /// The number of redirects when more are followed than allowed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        long_chain: Option<usize>,
+    },
+}
+
+#[derive(Serialize)]
+struct Page<'a> {
+    url: &'a str,
+    #[serde(flatten)]
+    status: Status<'a>,
+}
+
+// the URLs sorted, each converted to a page only as it's written so the whole document is
+// never held in memory
+struct Pages<'a> {
+    entries: Vec<(&'a Url, &'a LinkMapValue)>,
+    redirect_issues: &'a HashMap<Url, RedirectIssue>,
+}
+
+impl Serialize for Pages<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.entries.iter().map(|(url, value)| Page {
+            url: url.as_str(),
+            status: to_status(value, self.redirect_issues.get(*url)),
+        }))
+    }
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    root: &'a str,
+    pages: Pages<'a>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn to_error(err: &URLContentGetterError) -> Error<'_> {
+    match err {
+        URLContentGetterError::Request(code) => Error::Request { status_code: *code },
+        URLContentGetterError::Content(text) => Error::Content { message: text },
+        URLContentGetterError::Timeout => Error::Timeout,
+    }
+}
+
+fn to_alternate(alternate: &link_gatherer::Alternate) -> Alternate<'_> {
+    Alternate {
+        hreflang: &alternate.hreflang,
+        url: alternate.url.as_str(),
+    }
+}
+
+fn to_status<'a>(value: &'a LinkMapValue, issue: Option<&RedirectIssue>) -> Status<'a> {
+    match value {
+        LinkMapValue::Page(page) => Status::Ok {
+            status_code: page.status,
+            content_type: page.content_type.as_deref(),
+            content_length: page.content_length,
+            response_time_ms: page.response_time.as_millis(),
+            final_url: page.final_url.as_ref().map(Url::as_str),
+            title: page.title.as_deref(),
+            description: page.description.as_deref(),
+            canonical: page.canonical.as_ref().map(Url::as_str),
+            robots: page.robots.as_deref(),
+            robots_tag: &page.robots_tag,
+            noindex: page.noindex,
+            nofollow: page.nofollow,
+            alternates: page.alternates.iter().map(to_alternate).collect(),
+            crawled_at: page.crawled_at.to_string(),
+            last_modified: page.last_modified.map(|timestamp| timestamp.to_string()),
+            etag: page.etag.as_deref(),
+            unchanged: page.unchanged,
+            links: page
+                .links
+                .iter()
+                .map(|link| Link {
+                    url: link.url.as_str(),
+                    kind: link.kind.name(),
+                    nofollow: link.nofollow,
+                })
+                .collect(),
+        },
+        LinkMapValue::Error(err) => Status::Error {
+            error: to_error(err),
+        },
+        LinkMapValue::External(Ok(code)) => Status::External {
+            status_code: Some(*code),
+            error: None,
+        },
+        LinkMapValue::External(Err(err)) => Status::External {
+            status_code: None,
+            error: Some(to_error(err)),
+        },
+        LinkMapValue::Redirect(code, location) => Status::Redirect {
+            status_code: *code,
+            location: location.as_str(),
+            redirect_loop: issue == Some(&RedirectIssue::Loop),
+            long_chain: match issue {
+                Some(RedirectIssue::Long(hops)) => Some(*hops),
+                _ => None,
+            },
+        },
+        LinkMapValue::Skipped(SkipReason::Robots) => Status::Skipped { reason: "robots" },
+        LinkMapValue::Skipped(SkipReason::Limit) => Status::Skipped { reason: "limit" },
+        LinkMapValue::Skipped(SkipReason::Nofollow) => Status::Skipped { reason: "nofollow" },
+    }
+}
+
+/// Writes the link map as a JSON document with one entry per URL, sorted by URL, flagging the
+/// redirects in `redirect_issues`.
+pub fn to_json(
+    link_map: &LinkMap,
+    redirect_issues: &HashMap<Url, RedirectIssue>,
+    writer: impl Write,
+) -> serde_json::Result<()> {
+    let mut entries: Vec<_> = link_map.map.iter().collect();
+    entries.sort_by_key(|(url, _)| url.as_str());
+
+    serde_json::to_writer(
+        writer,
+        &Document {
+            root: link_map.root.as_str(),
+            pages: Pages {
+                entries,
+                redirect_issues,
+            },
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_gatherer::{LinkKind, PageRecord};
+    use crate::test_support::to_url;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    fn to_value(link_map: &LinkMap) -> Value {
+        let mut output = vec![];
+        to_json(link_map, &link_map.redirect_issues(5), &mut output).unwrap();
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    #[test]
+    fn serialises_pages() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(vec![].into()),
+        );
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(PageRecord {
+                links: vec![
+                    link_gatherer::Link {
+                        url: to_url("http://example.com/one"),
+                        kind: LinkKind::Anchor,
+                        nofollow: false,
+                    },
+                    link_gatherer::Link {
+                        url: to_url("http://example.com/logo.png"),
+                        kind: LinkKind::Image,
+                        nofollow: true,
+                    },
+                ],
+                status: 200,
+                content_type: Some("text/html".to_string()),
+                content_length: Some(1024),
+                response_time: Duration::from_millis(120),
+                final_url: Some(to_url("http://example.com")),
+                title: Some("Example".to_string()),
+                description: Some("An example".to_string()),
+                canonical: Some(to_url("http://example.com")),
+                robots: Some("noindex, follow".to_string()),
+                robots_tag: vec!["nofollow".to_string()],
+                noindex: true,
+                nofollow: true,
+                alternates: vec![link_gatherer::Alternate {
+                    hreflang: "fr".to_string(),
+                    url: to_url("http://example.com/fr/"),
+                }],
+                crawled_at: "2024-05-01T12:00:00Z".parse().unwrap(),
+                last_modified: Some("2015-10-21T07:28:00Z".parse().unwrap()),
+                etag: Some("\"v1\"".to_string()),
+                unchanged: true,
+                redirect: None,
+                redirects: vec![],
+            }),
+        );
+
+        assert_eq!(
+            to_value(&link_map),
+            json!({
+                "root": "http://example.com/",
+                "pages": [
+                    {
+                        "url": "http://example.com/",
+                        "status": "ok",
+                        "status_code": 200,
+                        "content_type": "text/html",
+                        "content_length": 1024,
+                        "response_time_ms": 120,
+                        "final_url": "http://example.com/",
+                        "title": "Example",
+                        "description": "An example",
+                        "canonical": "http://example.com/",
+                        "robots": "noindex, follow",
+                        "robots_tag": ["nofollow"],
+                        "noindex": true,
+                        "nofollow": true,
+                        "alternates": [{ "hreflang": "fr", "url": "http://example.com/fr/" }],
+                        "crawled_at": "2024-05-01T12:00:00Z",
+                        "last_modified": "2015-10-21T07:28:00Z",
+                        "etag": "\"v1\"",
+                        "unchanged": true,
+                        "links": [
+                            { "url": "http://example.com/one", "kind": "anchor" },
+                            { "url": "http://example.com/logo.png", "kind": "image", "nofollow": true }
+                        ]
+                    },
+                    {
+                        "url": "http://example.com/one",
+                        "status": "ok",
+                        "status_code": 0,
+                        "response_time_ms": 0,
+                        "crawled_at": "1970-01-01T00:00:00Z",
+                        "links": []
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn serialises_errors_skipped_external_and_redirects() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com/a"),
+            LinkMapValue::Error(URLContentGetterError::Request(404)),
+        );
+        link_map.add(
+            to_url("http://example.com/b"),
+            LinkMapValue::Error(URLContentGetterError::Content(
+                "something went wrong".to_string(),
+            )),
+        );
+        link_map.add(
+            to_url("http://example.com/bb"),
+            LinkMapValue::Error(URLContentGetterError::Timeout),
+        );
+        link_map.add(
+            to_url("http://example.com/c"),
+            LinkMapValue::Skipped(SkipReason::Robots),
+        );
+        link_map.add(
+            to_url("http://example.com/d"),
+            LinkMapValue::Skipped(SkipReason::Limit),
+        );
+        link_map.add(
+            to_url("http://other.com/e"),
+            LinkMapValue::External(Ok(200)),
+        );
+        link_map.add(
+            to_url("http://other.com/f"),
+            LinkMapValue::External(Err(URLContentGetterError::Request(410))),
+        );
+        link_map.add(
+            to_url("http://other.com/g"),
+            LinkMapValue::Redirect(301, to_url("http://other.com/e")),
+        );
+
+        assert_eq!(
+            to_value(&link_map)["pages"],
+            json!([
+                {
+                    "url": "http://example.com/a",
+                    "status": "error",
+                    "error": { "kind": "request", "status_code": 404 }
+                },
+                {
+                    "url": "http://example.com/b",
+                    "status": "error",
+                    "error": { "kind": "content", "message": "something went wrong" }
+                },
+                {
+                    "url": "http://example.com/bb",
+                    "status": "error",
+                    "error": { "kind": "timeout" }
+                },
+                { "url": "http://example.com/c", "status": "skipped", "reason": "robots" },
+                { "url": "http://example.com/d", "status": "skipped", "reason": "limit" },
+                { "url": "http://other.com/e", "status": "external", "status_code": 200 },
+                {
+                    "url": "http://other.com/f",
+                    "status": "external",
+                    "error": { "kind": "request", "status_code": 410 }
+                },
+                {
+                    "url": "http://other.com/g",
+                    "status": "redirect",
+                    "status_code": 301,
+                    "location": "http://other.com/e"
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn flags_redirect_loops_and_long_chains() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        for (from, to) in [("/a", "/b"), ("/b", "/a"), ("/c", "/d"), ("/d", "/e")] {
+            link_map.add(
+                to_url(&format!("http://example.com{}", from)),
+                LinkMapValue::Redirect(302, to_url(&format!("http://example.com{}", to))),
+            );
+        }
+        let mut output = vec![];
+        to_json(&link_map, &link_map.redirect_issues(1), &mut output).unwrap();
+        let value: Value = serde_json::from_slice(&output).unwrap();
+
+        let pages = value["pages"].as_array().unwrap();
+        assert_eq!(pages[0]["redirect_loop"], json!(true));
+        assert_eq!(pages[1]["redirect_loop"], json!(true));
+        assert_eq!(pages[2]["long_chain"], json!(2));
+        assert_eq!(pages[2].get("redirect_loop"), None);
+        assert_eq!(pages[3].get("long_chain"), None);
+    }
+}
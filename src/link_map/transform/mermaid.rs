@@ -0,0 +1,108 @@
+use super::graph::{Graph, NodeStatus};
+use crate::link_map::{LinkMap, RedirectIssue};
+use std::{collections::HashMap, fmt::Write};
+use url::Url;
+
+fn style(status: NodeStatus) -> &'static str {
+    match status {
+        NodeStatus::Ok => "fill:#c8f7c5,stroke:#2e7d32",
+        NodeStatus::Redirect => "fill:#e8d5f5,stroke:#6a1b9a",
+        NodeStatus::Error => "fill:#f8c4c4,stroke:#c62828",
+        NodeStatus::External => "fill:#e0e0e0,stroke:#757575",
+        NodeStatus::Blocked => "fill:#fff3b0,stroke:#f9a825",
+        NodeStatus::Unvisited => "fill:#cfe8fc,stroke:#1565c0",
+    }
+}
+
+// quotes are the only characters which can't appear in a quoted mermaid label
+fn label(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "#quot;"))
+}
+
+/// Renders the link graph as a Mermaid flowchart with nodes styled by status.
+pub fn to_mermaid(
+    link_map: &LinkMap,
+    cluster_dirs: bool,
+    redirect_issues: &HashMap<Url, RedirectIssue>,
+) -> Result<String, std::fmt::Error> {
+    let graph = Graph::new(link_map, cluster_dirs, redirect_issues);
+    let mut output = String::new();
+
+    writeln!(output, "flowchart LR")?;
+    for (i, node) in graph.nodes.iter().enumerate() {
+        match node.is_root {
+            true => writeln!(output, "  n{}(({}))", i, label(&node.label))?,
+            false => writeln!(output, "  n{}[{}]", i, label(&node.label))?,
+        }
+    }
+    for (from, to) in &graph.edges {
+        writeln!(output, "  n{} --> n{}", from, to)?;
+    }
+    for status in NodeStatus::ALL {
+        let ids: Vec<String> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.status == status)
+            .map(|(i, _)| format!("n{}", i))
+            .collect();
+        if !ids.is_empty() {
+            writeln!(output, "  classDef {} {}", status.name(), style(status))?;
+            writeln!(output, "  class {} {}", ids.join(","), status.name())?;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_gatherer::URLContentGetterError;
+    use crate::link_map::LinkMapValue;
+    use crate::test_support::to_url;
+
+    #[test]
+    fn renders_graph() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(vec![to_url("http://example.com/")].into()),
+        );
+        link_map.add(
+            to_url("http://example.com/two"),
+            LinkMapValue::Error(URLContentGetterError::Request(404)),
+        );
+
+        let expected = r#"flowchart LR
+  n0(("http://example.com/"))
+  n1["http://example.com/one"]
+  n2["http://example.com/two"]
+  n0 --> n1
+  n0 --> n2
+  n1 --> n0
+  classDef ok fill:#c8f7c5,stroke:#2e7d32
+  class n0,n1 ok
+  classDef error fill:#f8c4c4,stroke:#c62828
+  class n2 error
+"#;
+        assert_eq!(
+            to_mermaid(&link_map, false, &HashMap::new()),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(label(r#"a"b"#), "\"a#quot;b\"");
+    }
+}
@@ -1,2 +1,17 @@
+mod broken_links;
+mod coverage;
+mod dot;
+mod graph;
+mod json;
+mod mermaid;
+mod recrawl;
+mod sitemap;
 mod tree;
+pub use broken_links::to_broken_links_report;
+pub use coverage::to_coverage_report;
+pub use dot::to_dot;
+pub use json::to_json;
+pub use mermaid::to_mermaid;
+pub use recrawl::to_recrawl_summary;
+pub use sitemap::{to_sitemaps, SitemapFile, MAX_BYTES, MAX_URLS};
 pub use tree::to_tree;
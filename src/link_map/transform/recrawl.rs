@@ -0,0 +1,71 @@
+use crate::link_map::{LinkMap, LinkMapValue};
+use std::fmt::Write;
+
+/// The number of pages unchanged since the previous crawl, the number which changed and the
+/// number the previous crawl didn't have.
+pub fn to_recrawl_summary(
+    link_map: &LinkMap,
+    previous: &LinkMap,
+) -> Result<String, std::fmt::Error> {
+    let (mut unchanged, mut changed, mut new) = (0, 0, 0);
+    for (url, value) in &link_map.map {
+        match value {
+            LinkMapValue::Page(page) if page.unchanged => unchanged += 1,
+            LinkMapValue::Page(_) if previous.map.contains_key(url) => changed += 1,
+            LinkMapValue::Page(_) => new += 1,
+            _ => {}
+        }
+    }
+    let mut output = String::new();
+    writeln!(
+        output,
+        "Recrawl - {} pages unchanged, {} changed, {} new",
+        unchanged, changed, new
+    )?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_gatherer::{PageRecord, URLContentGetterError};
+    use crate::test_support::to_url;
+
+    #[test]
+    fn counts_unchanged_changed_and_new_pages() {
+        let mut previous = LinkMap::new(to_url("http://example.com"));
+        for path in ["/", "/one", "/two", "/three"] {
+            previous.add(
+                to_url("http://example.com").join(path).unwrap(),
+                LinkMapValue::Page(PageRecord::default()),
+            );
+        }
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(PageRecord::default()),
+        );
+        for path in ["/one", "/two"] {
+            link_map.add(
+                to_url("http://example.com").join(path).unwrap(),
+                LinkMapValue::Page(PageRecord {
+                    unchanged: true,
+                    ..PageRecord::default()
+                }),
+            );
+        }
+        link_map.add(
+            to_url("http://example.com/three"),
+            LinkMapValue::Error(URLContentGetterError::Request(500)),
+        );
+        link_map.add(
+            to_url("http://example.com/four"),
+            LinkMapValue::Page(PageRecord::default()),
+        );
+
+        assert_eq!(
+            to_recrawl_summary(&link_map, &previous),
+            Ok("Recrawl - 2 pages unchanged, 1 changed, 1 new\n".to_string())
+        );
+    }
+}
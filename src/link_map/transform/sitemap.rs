@@ -0,0 +1,301 @@
+use crate::{
+    link_gatherer::{media_type, PageRecord},
+    link_map::{LinkMap, LinkMapValue},
+};
+use jiff::Timestamp;
+use std::fmt::Write;
+use url::Url;
+
+/// Limits on a single sitemap file from the sitemaps.org protocol.
+pub const MAX_URLS: usize = 50_000;
+pub const MAX_BYTES: usize = 50 * 1024 * 1024;
+
+const URLSET_START: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#;
+const URLSET_END: &str = "</urlset>\n";
+
+#[derive(Debug, PartialEq)]
+pub struct SitemapFile {
+    pub name: String,
+    pub content: String,
+}
+
+struct Chunk {
+    entries: String,
+    count: usize,
+    last_modified: Option<Timestamp>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn entry(tag: &str, url: &str, last_modified: Option<Timestamp>) -> String {
+    let mut entry = format!("  <{}>\n    <loc>{}</loc>\n", tag, escape(url));
+    if let Some(last_modified) = last_modified {
+        entry += &format!("    <lastmod>{}</lastmod>\n", last_modified);
+    }
+    entry + &format!("  </{}>\n", tag)
+}
+
+// an indexable HTML page on the site's own host, treating a missing content type as HTML as
+// the link gatherer does
+pub(super) fn is_listed(root: &Url, url: &Url, page: &PageRecord) -> bool {
+    let html = page
+        .content_type
+        .as_deref()
+        .map(media_type)
+        .is_none_or(|media_type| {
+            media_type == "text/html" || media_type == "application/xhtml+xml"
+        });
+    html && !page.noindex && url.host_str() == root.host_str()
+}
+
+fn chunk(link_map: &LinkMap, max_urls: usize, max_bytes: usize) -> Vec<Chunk> {
+    let mut pages: Vec<(&Url, &PageRecord)> = link_map
+        .map
+        .iter()
+        .filter_map(|(url, value)| match value {
+            LinkMapValue::Page(page) if is_listed(&link_map.root, url, page) => Some((url, page)),
+            _ => None,
+        })
+        .collect();
+    pages.sort_by_key(|(url, _)| *url);
+
+    let max_entry_bytes = max_bytes.saturating_sub(URLSET_START.len() + URLSET_END.len());
+    let mut chunks: Vec<Chunk> = vec![];
+    for (url, page) in pages {
+        let last_modified = page.last_modified;
+        let entry = entry("url", url.as_str(), last_modified);
+        match chunks.last_mut() {
+            Some(chunk)
+                if chunk.count < max_urls
+                    && chunk.entries.len() + entry.len() <= max_entry_bytes =>
+            {
+                chunk.entries += &entry;
+                chunk.count += 1;
+                chunk.last_modified = chunk.last_modified.max(last_modified);
+            }
+            _ => chunks.push(Chunk {
+                entries: entry,
+                count: 1,
+                last_modified,
+            }),
+        }
+    }
+    chunks
+}
+
+/// Builds a sitemaps.org `urlset` of every successfully fetched HTML page on the root's host,
+/// leaving out pages marked noindex, named `sitemap.xml`.
+/// When the URLs don't fit within one file's limits they are split across `sitemap-{n}.xml`
+/// files and `sitemap.xml` becomes an index of those files, located relative to `base`.
+pub fn to_sitemaps(
+    link_map: &LinkMap,
+    base: &Url,
+    max_urls: usize,
+    max_bytes: usize,
+) -> Result<Vec<SitemapFile>, std::fmt::Error> {
+    let chunks = chunk(link_map, max_urls, max_bytes);
+    if chunks.len() <= 1 {
+        let entries = chunks.first().map(|c| c.entries.as_str()).unwrap_or("");
+        return Ok(vec![SitemapFile {
+            name: "sitemap.xml".to_string(),
+            content: format!("{}{}{}", URLSET_START, entries, URLSET_END),
+        }]);
+    }
+
+    let mut index = String::new();
+    writeln!(index, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        index,
+        r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#
+    )?;
+    let mut files = vec![];
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let name = format!("sitemap-{}.xml", i + 1);
+        let location = base.join(&name).map_err(|_| std::fmt::Error)?;
+        write!(
+            index,
+            "{}",
+            entry("sitemap", location.as_str(), chunk.last_modified)
+        )?;
+        files.push(SitemapFile {
+            name,
+            content: format!("{}{}{}", URLSET_START, chunk.entries, URLSET_END),
+        });
+    }
+    writeln!(index, "</sitemapindex>")?;
+    files.insert(
+        0,
+        SitemapFile {
+            name: "sitemap.xml".to_string(),
+            content: index,
+        },
+    );
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_gatherer::URLContentGetterError;
+    use crate::link_map::SkipReason;
+    use crate::test_support::to_url;
+
+    fn link_map() -> LinkMap {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one?a=1&b=2"),
+                    to_url("http://example.com/two"),
+                    to_url("http://example.com/three"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/one?a=1&b=2"),
+            LinkMapValue::Page(PageRecord {
+                last_modified: Some("2024-05-01T10:00:00Z".parse().unwrap()),
+                ..PageRecord::default()
+            }),
+        );
+        link_map.add(
+            to_url("http://example.com/two"),
+            LinkMapValue::Error(URLContentGetterError::Request(404)),
+        );
+        link_map.add(
+            to_url("http://example.com/three"),
+            LinkMapValue::Skipped(SkipReason::Robots),
+        );
+        link_map
+    }
+
+    #[test]
+    fn writes_urlset_of_fetched_pages() {
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>http://example.com/</loc>
+  </url>
+  <url>
+    <loc>http://example.com/one?a=1&amp;b=2</loc>
+    <lastmod>2024-05-01T10:00:00Z</lastmod>
+  </url>
+</urlset>
+"#;
+        assert_eq!(
+            to_sitemaps(
+                &link_map(),
+                &to_url("http://example.com/"),
+                MAX_URLS,
+                MAX_BYTES
+            ),
+            Ok(vec![SitemapFile {
+                name: "sitemap.xml".to_string(),
+                content: expected.to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn splits_into_index_past_url_limit() {
+        let files = to_sitemaps(
+            &link_map(),
+            &to_url("http://example.com/maps/"),
+            1,
+            MAX_BYTES,
+        )
+        .unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["sitemap.xml", "sitemap-1.xml", "sitemap-2.xml"]);
+
+        let expected_index = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap>
+    <loc>http://example.com/maps/sitemap-1.xml</loc>
+  </sitemap>
+  <sitemap>
+    <loc>http://example.com/maps/sitemap-2.xml</loc>
+    <lastmod>2024-05-01T10:00:00Z</lastmod>
+  </sitemap>
+</sitemapindex>
+"#;
+        assert_eq!(files[0].content, expected_index);
+        assert!(files[1].content.contains("<loc>http://example.com/</loc>"));
+        assert!(files[2]
+            .content
+            .contains("<loc>http://example.com/one?a=1&amp;b=2</loc>"));
+    }
+
+    #[test]
+    fn splits_past_byte_limit() {
+        let max_bytes = URLSET_START.len() + URLSET_END.len() + 120;
+        let files = to_sitemaps(
+            &link_map(),
+            &to_url("http://example.com/"),
+            MAX_URLS,
+            max_bytes,
+        )
+        .unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files[1..].iter().all(|f| f.content.len() <= max_bytes));
+    }
+
+    fn listed(url: &str, page: PageRecord) -> bool {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(to_url(url), LinkMapValue::Page(page));
+        to_sitemaps(
+            &link_map,
+            &to_url("http://example.com/"),
+            MAX_URLS,
+            MAX_BYTES,
+        )
+        .unwrap()[0]
+            .content
+            .contains(&format!("<loc>{}</loc>", url))
+    }
+
+    #[test]
+    fn leaves_out_noindex_pages() {
+        let page = |noindex| PageRecord {
+            content_type: Some("text/html; charset=utf-8".to_string()),
+            noindex,
+            ..PageRecord::default()
+        };
+        assert!(listed("http://example.com/thanks", page(false)));
+        assert!(!listed("http://example.com/thanks", page(true)));
+    }
+
+    #[test]
+    fn leaves_out_resources_other_than_html() {
+        let page = |content_type: &str| PageRecord {
+            content_type: Some(content_type.to_string()),
+            ..PageRecord::default()
+        };
+        assert!(listed(
+            "http://example.com/page",
+            page("application/xhtml+xml")
+        ));
+        assert!(!listed(
+            "http://example.com/report.pdf",
+            page("application/pdf")
+        ));
+        assert!(!listed("http://example.com/site.css", page("text/css")));
+    }
+
+    #[test]
+    fn leaves_out_pages_on_other_hosts() {
+        assert!(!listed("http://blog.example.com/", PageRecord::default()));
+        assert!(!listed("http://other.com/", PageRecord::default()));
+        assert!(listed("https://example.com/secure", PageRecord::default()));
+    }
+}
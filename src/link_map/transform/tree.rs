@@ -1,57 +1,55 @@
-use crate::{link_gatherer::URLContentGetterError, link_map::LinkMap, link_map::LinkMapValue};
+use crate::{
+    link_gatherer::URLContentGetterError,
+    link_map::{LinkMap, LinkMapValue, RedirectIssue, SkipReason},
+};
 use std::collections::{HashMap, VecDeque};
 use std::fmt::Write;
+use url::Url;
 
 #[derive(Clone, Debug)]
 struct Level(i32);
 
 struct Item {
-    url: String,
+    url: Url,
     level: Level,
     active: Vec<bool>,
-    parents: HashMap<String, ()>,
+    parents: HashMap<Url, ()>,
 }
 
-struct CountMap(HashMap<String, i32>);
+struct CountMap(HashMap<Url, i32>);
 
 impl CountMap {
     pub fn new() -> Self {
         CountMap(HashMap::new())
     }
-    pub fn increment(&mut self, url: &str) {
+    pub fn increment(&mut self, url: &Url) {
         match self.0.get_mut(url) {
             Some(val) => {
                 *val += 1;
             }
             None => {
-                self.0.insert(url.to_string(), 1);
+                self.0.insert(url.clone(), 1);
             }
         };
     }
-    pub fn decrement(&mut self, url: &str) {
-        match self.0.get_mut(url) {
-            Some(val) => {
-                *val -= 1;
-            }
-            None => {}
-        };
+    pub fn decrement(&mut self, url: &Url) {
+        if let Some(val) = self.0.get_mut(url) {
+            *val -= 1;
+        }
     }
 
-    pub fn processed(&mut self, url: &str) {
-        match self.0.get_mut(url) {
-            Some(val) => {
-                *val = 100;
-            }
-            None => {}
-        };
+    pub fn processed(&mut self, url: &Url) {
+        if let Some(val) = self.0.get_mut(url) {
+            *val = 100;
+        }
     }
 
-    pub fn is_queued_for_processing(&mut self, url: &str) -> bool {
+    pub fn is_queued_for_processing(&mut self, url: &Url) -> bool {
         self.0.get(url) > Some(&0)
     }
 }
 
-fn get_indent(level: &Level, active_levels: &Vec<bool>, is_tail: bool) -> String {
+fn get_indent(level: &Level, active_levels: &[bool], is_tail: bool) -> String {
     match level.0 {
         0 => "".to_string(),
         lev => (1..=lev)
@@ -65,10 +63,7 @@ fn get_indent(level: &Level, active_levels: &Vec<bool>, is_tail: bool) -> String
                     }
                 } else {
                     match active_levels.get((x - 1) as usize) {
-                        Some(active) => match active {
-                            true => out += "│  ",
-                            _ => out += "   ",
-                        },
+                        Some(true) => out += "│  ",
                         _ => out += "   ",
                     };
                 }
@@ -79,12 +74,14 @@ fn get_indent(level: &Level, active_levels: &Vec<bool>, is_tail: bool) -> String
 }
 
 fn get_next_level(dfs: &VecDeque<Item>) -> Level {
-    dfs.front()
-        .and_then(|i| Some(i.level.clone()))
-        .unwrap_or(Level(-1))
+    dfs.front().map(|i| i.level.clone()).unwrap_or(Level(-1))
 }
 
-pub fn to_tree(link_map: &LinkMap) -> Result<String, std::fmt::Error> {
+/// Renders the pages as a tree, flagging the redirects in `redirect_issues`.
+pub fn to_tree(
+    link_map: &LinkMap,
+    redirect_issues: &HashMap<Url, RedirectIssue>,
+) -> Result<String, std::fmt::Error> {
     let mut output = String::new();
 
     let mut visited = CountMap::new();
@@ -105,7 +102,7 @@ pub fn to_tree(link_map: &LinkMap) -> Result<String, std::fmt::Error> {
         visited.decrement(&url);
         let next_level = get_next_level(&dfs);
         let is_tail = level.0 <= next_level.0;
-        let mut new_active = Vec::from(active.clone());
+        let mut new_active = active.clone();
         if level.0 > 0 {
             new_active.push(level.0 == next_level.0);
         }
@@ -117,13 +114,13 @@ pub fn to_tree(link_map: &LinkMap) -> Result<String, std::fmt::Error> {
             cycle += " 🔗"
         } else {
             visited.processed(&url);
-            if let Some(LinkMapValue::Links(links)) = link_map.map.get(&url) {
+            if let Some(links) = link_map.map.get(&url).map(LinkMapValue::links) {
                 let mut new_parents = parents.clone();
                 new_parents.insert(url.clone(), ());
                 for link in links.iter().rev() {
-                    visited.increment(&link);
+                    visited.increment(link);
                     dfs.push_front(Item {
-                        url: link.clone(),
+                        url: (*link).clone(),
                         active: new_active.clone(),
                         level: Level(level.0 + 1),
                         parents: new_parents.clone(),
@@ -138,78 +135,115 @@ pub fn to_tree(link_map: &LinkMap) -> Result<String, std::fmt::Error> {
                 Some(LinkMapValue::Error(err)) => match err {
                     URLContentGetterError::Request(code) => format!(" - 😵 {}", code),
                     URLContentGetterError::Content(text) => format!(" - 😵 \"{}\"", text),
+                    URLContentGetterError::Timeout => " - 😵 ⏱️ timed out".to_string(),
+                },
+                Some(LinkMapValue::Skipped(SkipReason::Robots)) => {
+                    " - 🤖 blocked by robots.txt".to_string()
+                }
+                Some(LinkMapValue::Skipped(SkipReason::Limit)) => {
+                    " - 🚧 not visited (limit)".to_string()
+                }
+                Some(LinkMapValue::Skipped(SkipReason::Nofollow)) => {
+                    " - 🙈 not visited (nofollow)".to_string()
+                }
+                Some(LinkMapValue::Page(page)) if page.noindex => " - 🚫 noindex".to_string(),
+                Some(LinkMapValue::External(result)) => match result {
+                    Ok(code) => format!(" - 🌐 {}", code),
+                    Err(URLContentGetterError::Request(code)) => format!(" - 🌐 😵 {}", code),
+                    Err(URLContentGetterError::Content(text)) => format!(" - 🌐 😵 \"{}\"", text),
+                    Err(URLContentGetterError::Timeout) => " - 🌐 😵 ⏱️ timed out".to_string(),
+                },
+                Some(LinkMapValue::Redirect(code, _)) => match redirect_issues.get(&url) {
+                    Some(issue) => format!(" - ↪️ {} ⚠️ {}", code, issue),
+                    None => format!(" - ↪️ {}", code),
                 },
                 _ => "".to_string(),
             },
             _ => "".to_string(),
         };
 
-        match write!(output, "{}{}{}{}\n", indent, url, cycle, error) {
-            Err(err) => return Err(err),
-            _ => (),
-        }
+        writeln!(output, "{}{}{}{}", indent, url, cycle, error)?;
     }
     Ok(output)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::link_gatherer::URLContentGetterError;
+    use crate::link_gatherer::{PageRecord, URLContentGetterError};
+    use crate::test_support::to_url;
 
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
 
     #[test]
     fn display_simple() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/three".to_string(),
-                "http://example.com/four".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/three"),
+                    to_url("http://example.com/four"),
+                ]
+                .into(),
+            ),
         );
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one
 │  ├──http://example.com/three
 │  └──http://example.com/four
 └──http://example.com/two
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_handles_overhang() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/three".to_string(),
-                "http://example.com/four".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/three"),
+                    to_url("http://example.com/four"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/two".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/five".to_string(),
-                "http://example.com/six".to_string(),
-            ]),
+            to_url("http://example.com/two"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/five"),
+                    to_url("http://example.com/six"),
+                ]
+                .into(),
+            ),
         );
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one
 │  ├──http://example.com/three
 │  └──http://example.com/four
@@ -217,43 +251,55 @@ mod tests {
    ├──http://example.com/five
    └──http://example.com/six
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_deeply_nested() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/three".to_string(),
-                "http://example.com/four".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/three"),
+                    to_url("http://example.com/four"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/three".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/five".to_string(),
-                "http://example.com/six".to_string(),
-            ]),
+            to_url("http://example.com/three"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/five"),
+                    to_url("http://example.com/six"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/six".to_string(),
-            LinkMapValue::Links(vec!["http://example.com/seven".to_string()]),
+            to_url("http://example.com/six"),
+            LinkMapValue::Page(vec![to_url("http://example.com/seven")].into()),
         );
         link_map.add(
-            "http://example.com/four".to_string(),
-            LinkMapValue::Links(vec!["http://example.com/eight".to_string()]),
+            to_url("http://example.com/four"),
+            LinkMapValue::Page(vec![to_url("http://example.com/eight")].into()),
         );
 
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one
 │  ├──http://example.com/three
 │  │  ├──http://example.com/five
@@ -263,37 +309,43 @@ mod tests {
 │     └──http://example.com/eight
 └──http://example.com/two
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_tail() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec!["http://example.com/one".to_string()]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(vec![to_url("http://example.com/one")].into()),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/two".to_string(),
-                "http://example.com/t_w_o".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/two"),
+                    to_url("http://example.com/t_w_o"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/two".to_string(),
-            LinkMapValue::Links(vec!["http://example.com/three".to_string()]),
+            to_url("http://example.com/two"),
+            LinkMapValue::Page(vec![to_url("http://example.com/three")].into()),
         );
         link_map.add(
-            "http://example.com/three".to_string(),
-            LinkMapValue::Links(vec!["http://example.com/four".to_string()]),
+            to_url("http://example.com/three"),
+            LinkMapValue::Page(vec![to_url("http://example.com/four")].into()),
         );
         link_map.add(
-            "http://example.com/four".to_string(),
-            LinkMapValue::Links(vec!["http://example.com/five".to_string()]),
+            to_url("http://example.com/four"),
+            LinkMapValue::Page(vec![to_url("http://example.com/five")].into()),
         );
 
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 └──http://example.com/one
    ├──http://example.com/two
    │  └──http://example.com/three
@@ -301,143 +353,331 @@ mod tests {
    │        └──http://example.com/five
    └──http://example.com/t_w_o
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_shows_cycles() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/three".to_string(),
-                "http://example.com".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/three"),
+                    to_url("http://example.com"),
+                ]
+                .into(),
+            ),
         );
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one
 │  ├──http://example.com/three
-│  └──http://example.com ⟳
+│  └──http://example.com/ ⟳
 └──http://example.com/two
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_favours_shallower_nesting() {
         // if a url appears nearer the root that url should show the expanded links
         // other references to that url should show the link symbol 🔗
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/two".to_string(),
-                "http://example.com".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/two"),
+                    to_url("http://example.com"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/two".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com".to_string(),
-                "http://example.com/one".to_string(),
-            ]),
+            to_url("http://example.com/two"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com"),
+                    to_url("http://example.com/one"),
+                ]
+                .into(),
+            ),
         );
 
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one
 │  ├──http://example.com/two 🔗
-│  └──http://example.com ⟳
+│  └──http://example.com/ ⟳
 └──http://example.com/two
-   ├──http://example.com ⟳
+   ├──http://example.com/ ⟳
    └──http://example.com/one 🔗
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_with_gap() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/two".to_string(),
-                "http://example.com/three".to_string(),
-            ]),
+            to_url("http://example.com/one"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/two"),
+                    to_url("http://example.com/three"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/three".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com".to_string(),
-                "http://example.com/one".to_string(),
-            ]),
+            to_url("http://example.com/three"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com"),
+                    to_url("http://example.com/one"),
+                ]
+                .into(),
+            ),
         );
 
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one
 │  ├──http://example.com/two 🔗
 │  └──http://example.com/three
-│     ├──http://example.com ⟳
+│     ├──http://example.com/ ⟳
 │     └──http://example.com/one ⟳
 └──http://example.com/two
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
     }
 
     #[test]
     fn display_with_error() {
-        let mut link_map = LinkMap::new("http://example.com".to_string());
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
         link_map.add(
-            "http://example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/one".to_string(),
-                "http://example.com/two".to_string(),
-            ]),
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/two"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/one".to_string(),
+            to_url("http://example.com/one"),
             LinkMapValue::Error(URLContentGetterError::Request(401)),
         );
         link_map.add(
-            "http://example.com/two".to_string(),
-            LinkMapValue::Links(vec![
-                "http://example.com/three".to_string(),
-                "http://example.com/one".to_string(),
-            ]),
+            to_url("http://example.com/two"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/three"),
+                    to_url("http://example.com/one"),
+                ]
+                .into(),
+            ),
         );
         link_map.add(
-            "http://example.com/three".to_string(),
+            to_url("http://example.com/three"),
             LinkMapValue::Error(URLContentGetterError::Content(
                 "something went wrong".to_string(),
             )),
         );
 
-        let expected = r#"http://example.com
+        let expected = r#"http://example.com/
 ├──http://example.com/one - 😵 401
 └──http://example.com/two
    ├──http://example.com/three - 😵 "something went wrong"
    └──http://example.com/one 🔗
 "#;
-        assert_eq!(to_tree(&link_map), Ok(expected.to_string()));
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn display_with_skipped_and_noindex() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/one"),
+                    to_url("http://example.com/private"),
+                    to_url("http://example.com/sponsor"),
+                    to_url("http://example.com/thanks"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/one"),
+            LinkMapValue::Skipped(SkipReason::Limit),
+        );
+        link_map.add(
+            to_url("http://example.com/thanks"),
+            LinkMapValue::Page(PageRecord {
+                noindex: true,
+                ..PageRecord::default()
+            }),
+        );
+        link_map.add(
+            to_url("http://example.com/private"),
+            LinkMapValue::Skipped(SkipReason::Robots),
+        );
+        link_map.add(
+            to_url("http://example.com/sponsor"),
+            LinkMapValue::Skipped(SkipReason::Nofollow),
+        );
+
+        let expected = r#"http://example.com/
+├──http://example.com/one - 🚧 not visited (limit)
+├──http://example.com/private - 🤖 blocked by robots.txt
+├──http://example.com/sponsor - 🙈 not visited (nofollow)
+└──http://example.com/thanks - 🚫 noindex
+"#;
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn display_with_external() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://other.com/one"),
+                    to_url("http://other.com/two"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://other.com/one"),
+            LinkMapValue::External(Ok(200)),
+        );
+        link_map.add(
+            to_url("http://other.com/two"),
+            LinkMapValue::External(Err(URLContentGetterError::Request(404))),
+        );
+
+        let expected = r#"http://example.com/
+├──http://other.com/one - 🌐 200
+└──http://other.com/two - 🌐 😵 404
+"#;
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn display_with_redirects() {
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://example.com/old"),
+                    to_url("http://example.com/loop"),
+                ]
+                .into(),
+            ),
+        );
+        link_map.add(
+            to_url("http://example.com/old"),
+            LinkMapValue::Redirect(301, to_url("http://example.com/new")),
+        );
+        link_map.add(
+            to_url("http://example.com/new"),
+            LinkMapValue::Page(vec![].into()),
+        );
+        link_map.add(
+            to_url("http://example.com/loop"),
+            LinkMapValue::Redirect(302, to_url("http://example.com/loop/")),
+        );
+        link_map.add(
+            to_url("http://example.com/loop/"),
+            LinkMapValue::Redirect(302, to_url("http://example.com/loop")),
+        );
+
+        let expected = r#"http://example.com/
+├──http://example.com/old - ↪️ 301
+│  └──http://example.com/new
+└──http://example.com/loop - ↪️ 302 ⚠️ redirect loop
+   └──http://example.com/loop/ - ↪️ 302 ⚠️ redirect loop
+      └──http://example.com/loop ⟳
+"#;
+        assert_eq!(
+            to_tree(&link_map, &link_map.redirect_issues(5)),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn display_flags_redirect_chains_longer_than_max() {
+        let mut link_map = LinkMap::new(to_url("http://example.com/0"));
+        for i in 0..3 {
+            link_map.add(
+                to_url(&format!("http://example.com/{}", i)),
+                LinkMapValue::Redirect(301, to_url(&format!("http://example.com/{}", i + 1))),
+            );
+        }
+
+        let tree = to_tree(&link_map, &link_map.redirect_issues(2)).unwrap();
+        assert_eq!(
+            tree.lines().next(),
+            Some("http://example.com/0 - ↪️ 301 ⚠️ 3 redirects")
+        );
+        assert!(tree.lines().nth(1).unwrap().ends_with("/1 - ↪️ 301"));
+
+        let tree = to_tree(&link_map, &link_map.redirect_issues(3)).unwrap();
+        assert_eq!(tree.lines().next(), Some("http://example.com/0 - ↪️ 301"));
     }
 }
@@ -1,44 +1,642 @@
+mod canonicaliser;
 mod link_gatherer;
 mod link_map;
+mod robots;
+mod scope;
 mod site_tracer;
+mod sitemap;
+#[cfg(test)]
+mod test_support;
 
-use link_gatherer::Page;
-use site_tracer::SiteTracer;
+use canonicaliser::{Canonicaliser, QueryParams, TrailingSlash, TRACKING_PARAMS};
+use link_gatherer::{
+    parse_header, ClientConfig, Compression, Credentials, CssExtractor, ExtractorRegistry,
+    FeedExtractor, HostRewrite, HttpClient, JsonExtractor, LinkKind, LinkPolicy, Login, Page,
+    RedirectPolicy, Resolve, SitemapExtractor, UriListExtractor,
+};
+use scope::{SchemePolicy, Scope};
+use site_tracer::{Checkpoint, Politeness, SiteTracer};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
 
 use clap::Parser;
+use reqwest::{
+    cookie::Jar,
+    header::{HeaderName, HeaderValue},
+};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Format {
+    /// An indented tree of links from the base URL
+    Tree,
+    /// A JSON document listing the links or error for every URL
+    Json,
+    /// A Graphviz DOT digraph of every link
+    Dot,
+    /// A Mermaid flowchart of every link
+    Mermaid,
+}
+
+/// A format other than HTML to gather links from
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Extractor {
+    /// RSS and Atom feeds
+    Feed,
+    /// `text/uri-list` documents listing one URL per line
+    UriList,
+    /// XML sitemaps, reading any other XML as a feed
+    Sitemap,
+    /// Stylesheets' `url()`s and `@import`s
+    Css,
+}
 
 #[derive(Parser, Debug)]
 #[command(version)]
 pub struct Cli {
     /// The base URL to begin from
-    #[arg(short, long)]
-    url: String,
+    #[arg(short, long, required_unless_present = "resume")]
+    url: Option<Url>,
     #[arg(short, long)]
     log_level: Option<String>,
+    #[arg(short, long, value_enum, default_value_t = Format::Tree)]
+    format: Format,
+    /// Collapse pages into one node per directory in `dot` and `mermaid` output
+    #[arg(long)]
+    cluster_dirs: bool,
+    /// Flag redirects followed by more than this many redirects in a row before reaching a page
+    #[arg(long, default_value_t = 5)]
+    max_redirect_chain: usize,
+    /// Write the output to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Also write a sitemap of every fetched page to this directory
+    #[arg(long)]
+    sitemap_dir: Option<PathBuf>,
+    /// URL the sitemap files will be served from, used when they are split [default: the base URL's root]
+    #[arg(long, requires = "sitemap_dir")]
+    sitemap_base: Option<Url>,
+    /// Treat URLs differing only by fragment as distinct pages
+    #[arg(long)]
+    keep_fragments: bool,
+    /// Remove all query params when comparing URLs
+    #[arg(long, conflicts_with_all = ["allow_query_param", "drop_query_param"])]
+    drop_query: bool,
+    /// Only keep query params matching this name (trailing `*` matches a prefix), repeatable
+    #[arg(long, conflicts_with = "drop_query_param")]
+    allow_query_param: Vec<String>,
+    /// Remove query params matching this name (trailing `*` matches a prefix), repeatable
+    #[arg(long)]
+    drop_query_param: Vec<String>,
+    /// Remove common tracking and session query params e.g. utm_*, jsessionid
+    #[arg(long, conflicts_with_all = ["drop_query", "allow_query_param"])]
+    drop_tracking_params: bool,
+    /// Sort query params by name when comparing URLs
+    #[arg(long)]
+    sort_query: bool,
+    #[arg(long, value_enum, default_value_t = TrailingSlash::Keep)]
+    trailing_slash: TrailingSlash,
+    /// Which schemes on the root's host are part of the site
+    #[arg(long, value_enum, default_value_t = SchemePolicy::Upgrade)]
+    scheme: SchemePolicy,
+    /// Also visit subdomains of the root's host
+    #[arg(long)]
+    include_subdomains: bool,
+    /// Only visit URLs whose path begins with this prefix e.g. /docs/
+    #[arg(long)]
+    path_prefix: Option<String>,
+    /// Visit this host as part of the site, repeatable
+    #[arg(long)]
+    allow_host: Vec<String>,
+    /// Also start from the pages in the site's sitemaps and report how they compare to the pages found by links
+    #[arg(long)]
+    sitemap_seeds: bool,
+    /// Check links to other sites respond successfully, without following them, and report broken links
+    #[arg(long)]
+    check_external: bool,
+    /// Kinds of link to follow, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = LinkPolicy::default().follow)]
+    follow: Vec<LinkKind>,
+    /// Kinds of link to only check respond successfully, comma separated e.g. image,script. Give
+    /// no kinds to check none
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        num_args = 0..,
+        default_values_t = LinkPolicy::default().check
+    )]
+    check: Vec<LinkKind>,
+    /// Don't follow rel="nofollow" links or links on pages whose meta robots or X-Robots-Tag is nofollow
+    #[arg(long)]
+    respect_nofollow: bool,
+    /// Formats other than HTML to gather links from, comma separated. Other resources are
+    /// recorded without being downloaded. Stylesheets are only parsed when `link` or `style`
+    /// links are followed
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [Extractor::Feed, Extractor::UriList, Extractor::Sitemap, Extractor::Css]
+    )]
+    extract: Vec<Extractor>,
+    /// Gather links from JSON at this JSON pointer, where `*` matches every item e.g. /items/*/url, repeatable
+    #[arg(long)]
+    json_pointer: Vec<String>,
+    /// Abandon pages with a body larger than this many bytes
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    max_body_size: u64,
+    /// Visit URLs even when robots.txt disallows them
+    #[arg(long)]
+    ignore_robots: bool,
+    /// Number of pages fetched concurrently across all hosts
+    #[arg(long, default_value_t = 100)]
+    workers: u16,
+    /// Maximum concurrent requests to any one host
+    #[arg(long, default_value_t = 8)]
+    max_in_flight_per_host: u16,
+    /// Minimum milliseconds between requests to any one host
+    #[arg(long)]
+    min_delay_ms: Option<u64>,
+    /// Maximum requests per second to any one host
+    #[arg(long)]
+    requests_per_sec: Option<f64>,
+    /// Don't wait for the Crawl-delay requested in robots.txt
+    #[arg(long)]
+    ignore_crawl_delay: bool,
+    /// Maximum number of links to follow from the base URL
+    #[arg(long)]
+    max_depth: Option<u16>,
+    /// Maximum number of pages to visit
+    #[arg(long)]
+    max_pages: Option<usize>,
+    /// Periodically save the crawl's progress to this file, and on Ctrl-C, so it can be resumed
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Minimum seconds between checkpoints
+    #[arg(long, default_value_t = 30)]
+    checkpoint_interval_secs: u64,
+    /// Continue the crawl saved in this checkpoint, which is kept up to date unless --checkpoint
+    /// is given. Other options should match those of the original crawl
+    #[arg(long, conflicts_with_all = ["url", "sitemap_seeds"])]
+    resume: Option<PathBuf>,
+    /// Checkpoint saved at the end of a previous crawl. Pages are only downloaded again if they
+    /// changed since, according to their ETag or Last-Modified
+    #[arg(long)]
+    previous: Option<PathBuf>,
+    /// Send this header to the site's hosts as `Name: value`, repeatable
+    #[arg(long, value_parser = parse_header)]
+    header: Vec<(HeaderName, HeaderValue)>,
+    /// Send these HTTP basic auth credentials to the site's hosts as `username[:password]`
+    #[arg(long)]
+    basic_auth: Option<String>,
+    /// Send the bearer token in this environment variable to the site's hosts
+    #[arg(long)]
+    bearer_token_env: Option<String>,
+    /// Load cookies for the site's hosts from this Netscape cookies.txt file
+    #[arg(long)]
+    cookies: Option<PathBuf>,
+    /// Log in with the form on this page before crawling, and again whenever the session expires
+    #[arg(long)]
+    login_url: Option<Url>,
+    /// CSS selector of the login form
+    #[arg(long, requires = "login_url", default_value = "form")]
+    login_form: String,
+    /// Value of a login form field as `name=value`, repeatable
+    #[arg(long, requires = "login_url", value_parser = parse_field)]
+    login_field: Vec<(String, String)>,
+    /// Value of a login form field read from an environment variable as `name=VAR`, repeatable
+    #[arg(long, requires = "login_url", value_parser = parse_field)]
+    login_field_env: Vec<(String, String)>,
+    /// Read HTTP client settings from this JSON file, overridden by any of the options below
+    #[arg(long)]
+    client_config: Option<PathBuf>,
+    /// [default: scrapey/1.0]
+    #[arg(long)]
+    user_agent: Option<String>,
+    /// Seconds to wait to connect to a server [default: 10]
+    #[arg(long)]
+    connect_timeout_secs: Option<u64>,
+    /// Seconds to wait for a whole response, including its body [default: 30]
+    #[arg(long)]
+    timeout_secs: Option<u64>,
+    /// Whether redirects are recorded hop by hop or followed by the client [default: record]
+    #[arg(long, value_enum)]
+    redirects: Option<RedirectPolicy>,
+    /// Most redirects followed for one request when following them [default: 10]
+    #[arg(long)]
+    max_redirects: Option<usize>,
+    /// Content encodings to request and decompress, comma separated, or none when empty
+    /// [default: gzip,brotli,deflate]
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 0..)]
+    decompress: Option<Vec<Compression>>,
+    /// Use HTTP/2 without negotiating it, for servers known to support it
+    #[arg(long)]
+    http2_prior_knowledge: bool,
+    /// Most idle connections kept open to each host
+    #[arg(long)]
+    pool_max_idle_per_host: Option<usize>,
+    /// Send every request through this proxy e.g. http://proxy:3128 or socks5://proxy:1080
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Hosts, domains (with a leading `.`), IPs or networks reached without the proxy, comma
+    /// separated
+    #[arg(long, value_delimiter = ',')]
+    no_proxy: Vec<String>,
+    /// PEM file of CA certificates to trust in addition to the system's, repeatable
+    #[arg(long)]
+    ca_cert: Vec<PathBuf>,
+    /// PEM file of a certificate to identify the crawler with mutual TLS
+    #[arg(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// PEM file of the client certificate's PKCS #8 private key
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// Accept invalid TLS certificates, including those of unknown CAs or for other hosts
+    #[arg(long)]
+    insecure: bool,
+    /// Connect to an address for a host on a port rather than looking it up, as
+    /// `host:port:addr` e.g. www.example.com:443:10.0.0.5, repeatable
+    #[arg(long)]
+    resolve: Vec<Resolve>,
+    /// Fetch URLs on one origin from another while reporting them on the first, as `from=to`
+    /// e.g. https://www.example.com=http://localhost:8080, repeatable
+    #[arg(long)]
+    rewrite_host: Vec<HostRewrite>,
+}
+
+fn parse_field(field: &str) -> Result<(String, String), String> {
+    match field.split_once('=') {
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None => Err("expected `name=value`".to_string()),
+    }
+}
+
+impl Cli {
+    fn canonicaliser(&self) -> Canonicaliser {
+        let mut drop_query_params = self.drop_query_param.clone();
+        if self.drop_tracking_params {
+            drop_query_params.extend(TRACKING_PARAMS.iter().map(|p| p.to_string()));
+        }
+        let query_params = if self.drop_query {
+            QueryParams::DropAll
+        } else if !self.allow_query_param.is_empty() {
+            QueryParams::Allow(self.allow_query_param.clone())
+        } else if !drop_query_params.is_empty() {
+            QueryParams::Drop(drop_query_params)
+        } else {
+            QueryParams::Keep
+        };
+        Canonicaliser {
+            strip_fragment: !self.keep_fragments,
+            query_params,
+            sort_query: self.sort_query,
+            trailing_slash: self.trailing_slash,
+            ..Canonicaliser::default()
+        }
+    }
+
+    fn scope(&self) -> Scope {
+        Scope {
+            scheme: self.scheme,
+            include_subdomains: self.include_subdomains,
+            path_prefix: self.path_prefix.clone(),
+            allowed_hosts: self.allow_host.clone(),
+        }
+    }
+
+    fn link_policy(&self) -> LinkPolicy {
+        LinkPolicy {
+            follow: self.follow.clone(),
+            check: self.check.clone(),
+        }
+    }
+
+    fn politeness(&self) -> Politeness {
+        Politeness {
+            max_in_flight_per_host: Some(self.max_in_flight_per_host),
+            min_delay: self.min_delay_ms.map(Duration::from_millis),
+            requests_per_sec: self.requests_per_sec,
+            respect_crawl_delay: !self.ignore_crawl_delay,
+        }
+    }
+
+    fn extractors(&self) -> ExtractorRegistry {
+        let mut extractors = ExtractorRegistry::default();
+        for extractor in &self.extract {
+            match extractor {
+                Extractor::Feed => extractors.register(FeedExtractor),
+                Extractor::UriList => extractors.register(UriListExtractor),
+                Extractor::Sitemap => extractors.register(SitemapExtractor),
+                Extractor::Css => {
+                    if self
+                        .follow
+                        .iter()
+                        .any(|kind| matches!(kind, LinkKind::Link | LinkKind::Style))
+                    {
+                        extractors.register(CssExtractor)
+                    }
+                }
+            }
+        }
+        if !self.json_pointer.is_empty() {
+            extractors.register(JsonExtractor {
+                pointers: self.json_pointer.clone(),
+            });
+        }
+        extractors
+    }
+
+    /// Credentials are only sent to the root's host and the allowed hosts.
+    fn credentials(&self, root: &Url) -> Result<Credentials, String> {
+        let bearer_token = match &self.bearer_token_env {
+            Some(name) => Some(
+                std::env::var(name)
+                    .map_err(|e| format!("Unable to read bearer token from {}: {}", name, e))?,
+            ),
+            None => None,
+        };
+        Ok(Credentials {
+            hosts: root
+                .host_str()
+                .into_iter()
+                .map(str::to_string)
+                .chain(self.allow_host.iter().cloned())
+                .collect(),
+            include_subdomains: self.include_subdomains,
+            headers: self.header.iter().cloned().collect(),
+            basic_auth: self
+                .basic_auth
+                .as_ref()
+                .map(|auth| match auth.split_once(':') {
+                    Some((username, password)) => {
+                        (username.to_string(), Some(password.to_string()))
+                    }
+                    None => (auth.to_string(), None),
+                }),
+            bearer_token,
+        })
+    }
+
+    /// Cookies set during the crawl are kept, starting from those loaded from a cookies file.
+    fn cookie_jar(&self, credentials: &Credentials) -> Result<Arc<Jar>, String> {
+        let jar = Jar::default();
+        if let Some(path) = &self.cookies {
+            let cookies_txt = std::fs::read_to_string(path)
+                .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+            credentials
+                .load_cookies(&jar, &cookies_txt, &self.rewrite_host)
+                .map_err(|e| format!("Unable to load {}: {:?}", path.display(), e))?;
+        }
+        Ok(Arc::new(jar))
+    }
+
+    fn login(&self) -> Result<Option<Login>, String> {
+        let Some(url) = &self.login_url else {
+            return Ok(None);
+        };
+        let mut fields = self.login_field.clone();
+        for (name, var) in &self.login_field_env {
+            let value = std::env::var(var)
+                .map_err(|e| format!("Unable to read login field {} from {}: {}", name, var, e))?;
+            fields.push((name.clone(), value));
+        }
+        Ok(Some(Login {
+            url: url.clone(),
+            form_selector: self.login_form.clone(),
+            fields,
+        }))
+    }
+
+    fn client_config(&self) -> Result<ClientConfig, String> {
+        let config = match &self.client_config {
+            Some(path) => ClientConfig::load(path)
+                .map_err(|e| format!("Unable to load {}: {:?}", path.display(), e))?,
+            None => ClientConfig::default(),
+        };
+        Ok(ClientConfig {
+            user_agent: self.user_agent.clone().unwrap_or(config.user_agent),
+            connect_timeout_secs: self.connect_timeout_secs.or(config.connect_timeout_secs),
+            timeout_secs: self.timeout_secs.or(config.timeout_secs),
+            redirects: self.redirects.unwrap_or(config.redirects),
+            max_redirects: self.max_redirects.unwrap_or(config.max_redirects),
+            decompress: self.decompress.clone().unwrap_or(config.decompress),
+            http2_prior_knowledge: self.http2_prior_knowledge || config.http2_prior_knowledge,
+            pool_max_idle_per_host: self
+                .pool_max_idle_per_host
+                .or(config.pool_max_idle_per_host),
+            proxy: self.proxy.clone().or(config.proxy),
+            no_proxy: match self.no_proxy.is_empty() {
+                true => config.no_proxy,
+                false => self.no_proxy.clone(),
+            },
+            ca_certs: match self.ca_cert.is_empty() {
+                true => config.ca_certs,
+                false => self.ca_cert.clone(),
+            },
+            client_cert: self.client_cert.clone().or(config.client_cert),
+            client_key: self.client_key.clone().or(config.client_key),
+            insecure: self.insecure || config.insecure,
+            resolve: config
+                .resolve
+                .into_iter()
+                .chain(self.resolve.iter().cloned())
+                .collect(),
+        })
+    }
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Cli::try_parse();
     match args {
         Ok(args) => {
-            if let Some(log_level) = args.log_level {
+            if let Some(log_level) = &args.log_level {
                 tracing_subscriber::fmt()
                     .with_env_filter(format!("aleister_crawly={}", log_level))
                     .compact()
                     .init();
             }
 
+            let resumed = match &args.resume {
+                Some(path) => match Checkpoint::load(path) {
+                    Ok(checkpoint) => Some(checkpoint),
+                    Err(e) => {
+                        eprintln!("Unable to resume from {}: {:?}", path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => None,
+            };
+            let root = match (&args.url, &resumed) {
+                (_, Some(checkpoint)) => checkpoint.link_map.root.clone(),
+                (Some(url), None) => url.clone(),
+                (None, None) => return ExitCode::FAILURE,
+            };
+            // opened before the crawl so a bad path fails fast rather than losing its results
+            let output = match &args.output {
+                Some(path) => match File::create(path) {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        eprintln!("Unable to create {}: {}", path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => None,
+            };
+            // and the sitemap directory created for the same reason
+            if let Some(dir) = &args.sitemap_dir {
+                if let Err(e) = std::fs::create_dir_all(dir) {
+                    eprintln!("Unable to create {}: {}", dir.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            let credentials = match args.credentials(&root) {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let login = match args.login() {
+                Ok(login) => login,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let cookies = match args.cookie_jar(&credentials) {
+                Ok(cookies) => cookies,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let client_config = match args.client_config() {
+                Ok(client_config) => client_config,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let client = match client_config.build(cookies) {
+                Ok(client) => client,
+                Err(e) => {
+                    eprintln!("Unable to create HTTP client: {:?}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let previous = match &args.previous {
+                Some(path) => match Checkpoint::load(path) {
+                    Ok(checkpoint) => Some(checkpoint.link_map),
+                    Err(e) => {
+                        eprintln!("Unable to load previous crawl {}: {:?}", path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => None,
+            };
             let st = SiteTracer {
-                link_getter: Page::new(reqwest::Client::new()),
-                worker_pool_size: 100,
-                max_retries: 3,
-                initial_retry_delay_ms: 250,
+                canonicaliser: args.canonicaliser(),
+                scope: args.scope(),
+                politeness: args.politeness(),
+                worker_pool_size: args.workers,
+                max_depth: args.max_depth,
+                max_pages: args.max_pages,
+                respect_robots: !args.ignore_robots,
+                seed_from_sitemaps: args.sitemap_seeds,
+                check_external: args.check_external,
+                link_policy: args.link_policy(),
+                respect_nofollow: args.respect_nofollow,
+                checkpoint: args.checkpoint.clone().or(args.resume.clone()),
+                checkpoint_interval: Duration::from_secs(args.checkpoint_interval_secs),
+                previous,
+                login,
+                user_agent: client_config.user_agent.clone(),
+                ..SiteTracer::new(
+                    Page::new(HttpClient {
+                        client,
+                        credentials,
+                        rewrites: args.rewrite_host.clone(),
+                        follow_redirects: client_config.follow_redirects(),
+                    })
+                    .with_extractors(args.extractors())
+                    .with_max_body_size(args.max_body_size),
+                )
+            };
+
+            let traced = match resumed {
+                Some(checkpoint) => st.resume(checkpoint).await,
+                None => st.trace(&root).await,
+            };
+            let link_map = match traced {
+                Ok(link_map) => link_map,
+                Err(e) => {
+                    eprintln!("Unable to trace {}: {}", root, e);
+                    return ExitCode::FAILURE;
+                }
             };
 
-            let link_map = st.trace(&args.url).await;
-            println!("\n{}", link_map.to_tree());
+            if args.sitemap_seeds {
+                eprintln!("\n{}", link_map.to_coverage_report());
+            }
+            if args.check_external || !args.check.is_empty() {
+                eprintln!("\n{}", link_map.to_broken_links_report());
+            }
+            if let Some(previous) = &st.previous {
+                eprintln!("\n{}", link_map.to_recrawl_summary(previous));
+            }
+
+            if let Some(dir) = &args.sitemap_dir {
+                let base = match &args.sitemap_base {
+                    Some(base) => base.clone(),
+                    None => link_map.root.join("/").unwrap_or(link_map.root.clone()),
+                };
+                for sitemap in link_map.to_sitemaps(&base) {
+                    let path = dir.join(&sitemap.name);
+                    if let Err(e) = std::fs::write(&path, sitemap.content) {
+                        eprintln!("Unable to write {}: {}", path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+
+            let mut writer: Box<dyn Write> = match output {
+                Some(file) => Box::new(BufWriter::new(file)),
+                None => Box::new(BufWriter::new(io::stdout().lock())),
+            };
+            let written = match args.format {
+                Format::Tree => write!(writer, "{}", link_map.to_tree(args.max_redirect_chain)),
+                Format::Dot => write!(
+                    writer,
+                    "{}",
+                    link_map.to_dot(args.cluster_dirs, args.max_redirect_chain)
+                ),
+                Format::Mermaid => write!(
+                    writer,
+                    "{}",
+                    link_map.to_mermaid(args.cluster_dirs, args.max_redirect_chain)
+                ),
+                Format::Json => link_map
+                    .write_json(&mut writer, args.max_redirect_chain)
+                    .map_err(io::Error::from)
+                    .and_then(|_| writeln!(writer)),
+            };
+            if let Err(e) = written.and_then(|_| writer.flush()) {
+                eprintln!("Unable to write output: {}", e);
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("{}", e);
+            ExitCode::from(e.exit_code() as u8)
         }
-        Err(e) => println!("{}", e.to_string()),
     }
 }
@@ -0,0 +1,414 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::link_gatherer::URLContentGetterError;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+/// The rules from a robots.txt file which apply to a particular user agent.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+    disallow_all: bool,
+    sitemaps: Vec<Url>,
+}
+
+// the product token is the user agent name without any version e.g. `scrapey` for `scrapey/1.0`
+fn product_token(user_agent: &str) -> String {
+    user_agent
+        .split('/')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn parse_groups(content: &str) -> Vec<Group> {
+    let mut groups: Vec<Group> = vec![];
+    let mut current = Group::default();
+    let mut in_rules = false;
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "user-agent" => {
+                if in_rules {
+                    groups.push(current);
+                    current = Group::default();
+                    in_rules = false;
+                }
+                current.user_agents.push(product_token(value));
+            }
+            "allow" | "disallow" if !current.user_agents.is_empty() => {
+                in_rules = true;
+                // an empty disallow allows everything so can be ignored
+                if !value.is_empty() {
+                    current.rules.push(Rule {
+                        allow: key.trim().eq_ignore_ascii_case("allow"),
+                        pattern: value.to_string(),
+                    });
+                }
+            }
+            "crawl-delay" if !current.user_agents.is_empty() => {
+                in_rules = true;
+                current.crawl_delay = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                    .map(Duration::from_secs_f64);
+            }
+            _ => (),
+        }
+    }
+    if !current.user_agents.is_empty() {
+        groups.push(current);
+    }
+    groups
+}
+
+// sitemap lines apply to every user agent so are collected regardless of group
+fn parse_sitemaps(content: &str) -> Vec<Url> {
+    content
+        .lines()
+        .filter_map(|line| line.split('#').next().unwrap_or("").split_once(':'))
+        .filter(|(key, _)| key.trim().eq_ignore_ascii_case("sitemap"))
+        .filter_map(|(_, value)| Url::parse(value.trim()).ok())
+        .collect()
+}
+
+/// Matches a robots.txt path pattern where `*` matches any sequence of characters
+/// and a trailing `$` anchors the pattern to the end of the path.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let mut remaining = path;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            match remaining.strip_prefix(part) {
+                Some(rest) => remaining = rest,
+                None => return false,
+            }
+        } else if i == parts.len() - 1 && anchored {
+            return remaining.ends_with(part);
+        } else {
+            match remaining.find(part) {
+                Some(idx) => remaining = &remaining[idx + part.len()..],
+                None => return false,
+            }
+        }
+    }
+    !anchored || remaining.is_empty()
+}
+
+impl Robots {
+    pub fn parse(content: &str, user_agent: &str) -> Self {
+        let token = product_token(user_agent);
+        let groups = parse_groups(content);
+        let matching: Vec<&Group> = match groups
+            .iter()
+            .filter(|g| g.user_agents.contains(&token))
+            .collect::<Vec<_>>()
+        {
+            specific if !specific.is_empty() => specific,
+            _ => groups
+                .iter()
+                .filter(|g| g.user_agents.iter().any(|ua| ua == "*"))
+                .collect(),
+        };
+        Robots {
+            rules: matching.iter().flat_map(|g| g.rules.clone()).collect(),
+            crawl_delay: matching.iter().find_map(|g| g.crawl_delay),
+            disallow_all: false,
+            sitemaps: parse_sitemaps(content),
+        }
+    }
+
+    /// Builds the rules from the result of fetching a robots.txt file. Client errors, and
+    /// redirects the client doesn't follow, mean there are no restrictions while any other
+    /// failure disallows everything.
+    pub fn from_response(
+        response: Result<String, URLContentGetterError>,
+        user_agent: &str,
+    ) -> Self {
+        match response {
+            Ok(content) => Robots::parse(&content, user_agent),
+            Err(URLContentGetterError::Request(status)) if (300..500).contains(&status) => {
+                Robots::default()
+            }
+            Err(err) => {
+                tracing::warn!("Unable to get robots.txt, disallowing all - {:?}", err);
+                Robots {
+                    disallow_all: true,
+                    ..Robots::default()
+                }
+            }
+        }
+    }
+
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        if path == "/robots.txt" {
+            return true;
+        }
+        if self.disallow_all {
+            return false;
+        }
+        // the most specific (longest) matching rule wins, with allow winning ties
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    /// Sitemaps listed with `Sitemap:` lines.
+    pub fn sitemaps(&self) -> &[Url] {
+        &self.sitemaps
+    }
+}
+
+pub fn robots_url(url: &Url) -> Option<Url> {
+    url.join("/robots.txt").ok()
+}
+
+// an origin's rules, set once they've been fetched
+type Entry = Arc<OnceCell<Arc<Robots>>>;
+
+/// Holds the robots.txt rules for each origin visited so they are only fetched once, with
+/// clones sharing the rules.
+#[derive(Debug, Default, Clone)]
+pub struct RobotsCache(Arc<Mutex<HashMap<String, Entry>>>);
+
+impl RobotsCache {
+    pub fn new() -> Self {
+        RobotsCache::default()
+    }
+
+    fn entry(&self, url: &Url) -> Entry {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(url.origin().ascii_serialization())
+            .or_default()
+            .clone()
+    }
+
+    /// The rules for the URL's origin, if they've been fetched.
+    pub fn get(&self, url: &Url) -> Option<Arc<Robots>> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&url.origin().ascii_serialization())
+            .and_then(|rules| rules.get().cloned())
+    }
+
+    /// The rules for the URL's origin, fetched with `fetch` the first time they're needed. Tasks
+    /// needing them while they're being fetched wait for that fetch rather than starting another.
+    pub async fn get_or_fetch<F, Fut>(&self, url: &Url, fetch: F) -> Arc<Robots>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Robots>,
+    {
+        self.entry(url)
+            .get_or_init(|| async { Arc::new(fetch().await) })
+            .await
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn is_allowed(robots: &Robots, path: &str) -> bool {
+        robots.is_allowed(&to_url("http://example.com").join(path).unwrap())
+    }
+
+    #[test]
+    fn selects_group_for_user_agent() {
+        let content = r#"
+User-agent: *
+Disallow: /
+
+User-agent: Scrapey
+Disallow: /private
+"#;
+        let robots = Robots::parse(content, "scrapey/1.0");
+        assert!(is_allowed(&robots, "/"));
+        assert!(!is_allowed(&robots, "/private/one"));
+
+        let robots = Robots::parse(content, "otherbot");
+        assert!(!is_allowed(&robots, "/"));
+    }
+
+    #[test]
+    fn merges_groups_and_shared_user_agent_lines() {
+        let content = r#"
+User-agent: otherbot
+User-agent: scrapey
+Disallow: /one
+
+User-agent: googlebot
+Disallow: /two
+
+User-agent: scrapey
+Disallow: /three
+"#;
+        let robots = Robots::parse(content, "scrapey");
+        assert!(!is_allowed(&robots, "/one"));
+        assert!(is_allowed(&robots, "/two"));
+        assert!(!is_allowed(&robots, "/three"));
+    }
+
+    #[test]
+    fn longest_match_wins_and_allow_wins_ties() {
+        let content = r#"
+User-agent: *
+Disallow: /docs/
+Allow: /docs/public/
+Allow: /shared
+Disallow: /shared
+"#;
+        let robots = Robots::parse(content, "scrapey");
+        assert!(!is_allowed(&robots, "/docs/private"));
+        assert!(is_allowed(&robots, "/docs/public/one"));
+        assert!(is_allowed(&robots, "/shared"));
+        assert!(is_allowed(&robots, "/other"));
+    }
+
+    #[test]
+    fn supports_wildcards_and_end_anchors() {
+        let content = r#"
+User-agent: *
+Disallow: /*.pdf$
+Disallow: /search*q=
+Disallow: /exact$
+"#;
+        let robots = Robots::parse(content, "scrapey");
+        assert!(!is_allowed(&robots, "/files/report.pdf"));
+        assert!(is_allowed(&robots, "/files/report.pdf.html"));
+        assert!(!is_allowed(&robots, "/search?lang=en&q=crawler"));
+        assert!(is_allowed(&robots, "/search?lang=en"));
+        assert!(!is_allowed(&robots, "/exact"));
+        assert!(is_allowed(&robots, "/exact/more"));
+    }
+
+    #[test]
+    fn ignores_comments_and_empty_disallow() {
+        let content = r#"
+# comment
+User-agent: * # everyone
+Disallow:
+"#;
+        let robots = Robots::parse(content, "scrapey");
+        assert!(is_allowed(&robots, "/anything"));
+    }
+
+    #[test]
+    fn parses_crawl_delay() {
+        let content = r#"
+User-agent: *
+Crawl-delay: 1.5
+"#;
+        let robots = Robots::parse(content, "scrapey");
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn parses_sitemaps() {
+        let content = r#"
+Sitemap: https://example.com/sitemap.xml
+User-agent: otherbot
+Disallow: /
+sitemap: https://cdn.example.com/sitemaps/index.xml.gz
+Sitemap: /relative.xml
+"#;
+        let robots = Robots::parse(content, "scrapey");
+        assert_eq!(
+            robots.sitemaps(),
+            &[
+                to_url("https://example.com/sitemap.xml"),
+                to_url("https://cdn.example.com/sitemaps/index.xml.gz")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_rules_once_per_origin() {
+        let cache = RobotsCache::new();
+        let fetches = Arc::new(Mutex::new(0));
+        let get = |url: &str| {
+            let (cache, fetches, url) = (cache.clone(), fetches.clone(), to_url(url));
+            tokio::spawn(async move {
+                cache
+                    .get_or_fetch(&url, || async {
+                        *fetches.lock().unwrap() += 1;
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        Robots::parse("User-agent: *\nDisallow: /private", "scrapey")
+                    })
+                    .await
+            })
+        };
+
+        let (one, two, other) = (
+            get("http://example.com/one"),
+            get("http://example.com/two"),
+            get("https://example.com/one"),
+        );
+        let (one, two, _) = (one.await.unwrap(), two.await.unwrap(), other.await.unwrap());
+
+        assert_eq!(*fetches.lock().unwrap(), 2);
+        assert!(Arc::ptr_eq(&one, &two));
+        assert_eq!(cache.get(&to_url("http://example.com/three")), Some(one));
+        assert_eq!(cache.get(&to_url("http://other.com/")), None);
+    }
+
+    #[test]
+    fn from_response_handles_errors() {
+        let not_found = Robots::from_response(Err(URLContentGetterError::Request(404)), "scrapey");
+        assert!(is_allowed(&not_found, "/one"));
+
+        let redirect = Robots::from_response(Err(URLContentGetterError::Request(301)), "scrapey");
+        assert!(is_allowed(&redirect, "/one"));
+
+        let server_error =
+            Robots::from_response(Err(URLContentGetterError::Request(503)), "scrapey");
+        assert!(!is_allowed(&server_error, "/one"));
+        assert!(is_allowed(&server_error, "/robots.txt"));
+    }
+}
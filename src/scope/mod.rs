@@ -0,0 +1,241 @@
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum SchemePolicy {
+    /// Only URLs with the same scheme as the root
+    Exact,
+    /// Also allow `https` URLs when the root is `http`
+    Upgrade,
+    /// Allow either of `http` and `https`
+    Any,
+}
+
+/// Decides which URLs belong to the site being traced relative to the root URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub scheme: SchemePolicy,
+    pub include_subdomains: bool,
+    pub path_prefix: Option<String>,
+    /// Hosts treated as part of the site in addition to the root's host.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope {
+            scheme: SchemePolicy::Upgrade,
+            include_subdomains: false,
+            path_prefix: None,
+            allowed_hosts: vec![],
+        }
+    }
+}
+
+impl Scope {
+    fn scheme_in_scope(&self, root: &Url, url: &Url) -> bool {
+        match self.scheme {
+            SchemePolicy::Exact => url.scheme() == root.scheme(),
+            SchemePolicy::Upgrade => {
+                url.scheme() == root.scheme()
+                    || (root.scheme() == "http" && url.scheme() == "https")
+            }
+            SchemePolicy::Any => true,
+        }
+    }
+
+    fn host_in_scope(&self, root: &Url, url: &Url) -> bool {
+        let (Some(root_host), Some(host)) = (root.host_str(), url.host_str()) else {
+            return false;
+        };
+        if host == root_host {
+            // a different port on the same host and scheme is a different site
+            return url.scheme() != root.scheme()
+                || url.port_or_known_default() == root.port_or_known_default();
+        }
+        (self.include_subdomains && host.ends_with(&format!(".{}", root_host)))
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+
+    fn path_in_scope(&self, url: &Url) -> bool {
+        match &self.path_prefix {
+            Some(prefix) => url.path().starts_with(prefix.as_str()),
+            None => true,
+        }
+    }
+
+    pub fn contains(&self, root: &Url, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+            && self.scheme_in_scope(root, url)
+            && self.host_in_scope(root, url)
+            && self.path_in_scope(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn contains(scope: &Scope, root: &str, url: &str) -> bool {
+        scope.contains(&to_url(root), &to_url(url))
+    }
+
+    #[test]
+    fn default_matches_exact_host() {
+        let scope = Scope::default();
+        assert!(contains(
+            &scope,
+            "http://example.com",
+            "http://example.com/one"
+        ));
+        assert!(contains(&scope, "http://example.com", "http://EXAMPLE.com"));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://www.example.com/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://example.com.evil.net/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://evilexample.com/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "mailto:someone@example.com"
+        ));
+    }
+
+    #[test]
+    fn different_port_is_out_of_scope() {
+        let scope = Scope::default();
+        assert!(contains(
+            &scope,
+            "http://example.com",
+            "http://example.com:80/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://example.com:8080/one"
+        ));
+    }
+
+    #[test]
+    fn includes_subdomains() {
+        let scope = Scope {
+            include_subdomains: true,
+            ..Scope::default()
+        };
+        assert!(contains(
+            &scope,
+            "http://example.com",
+            "http://docs.example.com/one"
+        ));
+        assert!(contains(
+            &scope,
+            "http://example.com",
+            "http://a.b.example.com/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://example.com.evil.net/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://notexample.com/one"
+        ));
+    }
+
+    #[test]
+    fn scheme_policies() {
+        let exact = Scope {
+            scheme: SchemePolicy::Exact,
+            ..Scope::default()
+        };
+        assert!(!contains(
+            &exact,
+            "http://example.com",
+            "https://example.com/one"
+        ));
+
+        let upgrade = Scope::default();
+        assert!(contains(
+            &upgrade,
+            "http://example.com",
+            "https://example.com/one"
+        ));
+        assert!(!contains(
+            &upgrade,
+            "https://example.com",
+            "http://example.com/one"
+        ));
+
+        let any = Scope {
+            scheme: SchemePolicy::Any,
+            ..Scope::default()
+        };
+        assert!(contains(
+            &any,
+            "https://example.com",
+            "http://example.com/one"
+        ));
+        assert!(!contains(
+            &any,
+            "https://example.com",
+            "ftp://example.com/one"
+        ));
+    }
+
+    #[test]
+    fn restricts_to_path_prefix() {
+        let scope = Scope {
+            path_prefix: Some("/docs/".to_string()),
+            ..Scope::default()
+        };
+        assert!(contains(
+            &scope,
+            "http://example.com/docs/",
+            "http://example.com/docs/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com/docs/",
+            "http://example.com/blog/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com/docs/",
+            "http://example.com/docs"
+        ));
+    }
+
+    #[test]
+    fn allows_extra_hosts() {
+        let scope = Scope {
+            allowed_hosts: vec!["cdn.example.net".to_string()],
+            ..Scope::default()
+        };
+        assert!(contains(
+            &scope,
+            "http://example.com",
+            "http://cdn.example.net/one"
+        ));
+        assert!(!contains(
+            &scope,
+            "http://example.com",
+            "http://other.example.net/one"
+        ));
+    }
+}
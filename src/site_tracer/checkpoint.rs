@@ -0,0 +1,103 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use crate::link_map::LinkMap;
+
+use super::process_heap::Process;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CheckpointError {
+    #[error("unable to read or write checkpoint")]
+    Io(String),
+    #[error("invalid checkpoint")]
+    Format(String),
+}
+
+/// The state of a trace, saved so that it can be resumed after being interrupted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Results of every URL processed so far
+    pub link_map: LinkMap,
+    /// Every URL queued, processed or skipped
+    pub seen: HashSet<Url>,
+    /// URLs queued or being processed when the checkpoint was taken, with their retry counts
+    pub pending: Vec<Process>,
+    /// Number of URLs queued to be fetched, excluding retries
+    pub pages: usize,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let content = fs::read(path).map_err(|err| CheckpointError::Io(err.to_string()))?;
+        serde_json::from_slice(&content).map_err(|err| CheckpointError::Format(err.to_string()))
+    }
+
+    /// Writes to a temporary file first so an interruption never leaves a partial checkpoint.
+    pub fn save(&self, path: &Path) -> Result<(), CheckpointError> {
+        let content =
+            serde_json::to_vec(self).map_err(|err| CheckpointError::Format(err.to_string()))?;
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, content)
+            .and_then(|_| fs::rename(&temp, path))
+            .map_err(|err| CheckpointError::Io(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        link_gatherer::{PageRecord, URLContentGetterError},
+        link_map::{LinkMapValue, SkipReason},
+        test_support::to_url,
+    };
+
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_checkpoint() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let mut link_map = LinkMap::new(to_url("http://example.com"));
+        link_map.add(
+            to_url("http://example.com"),
+            LinkMapValue::Page(PageRecord {
+                status: 200,
+                response_time: Duration::from_millis(120),
+                crawled_at: "2024-05-01T12:00:00Z".parse().unwrap(),
+                ..vec![to_url("http://example.com/one")].into()
+            }),
+        );
+        link_map.add(
+            to_url("http://example.com/two"),
+            LinkMapValue::Error(URLContentGetterError::Request(500)),
+        );
+        link_map.add(
+            to_url("http://example.com/three"),
+            LinkMapValue::Skipped(SkipReason::Robots),
+        );
+        link_map.sitemap.insert(to_url("http://example.com/one"));
+        let checkpoint = Checkpoint {
+            link_map,
+            seen: HashSet::from([
+                to_url("http://example.com"),
+                to_url("http://example.com/one"),
+            ]),
+            pending: vec![Process::new(&to_url("http://example.com/one"), 2, 1, &0)],
+            pages: 2,
+        };
+
+        assert_eq!(checkpoint.save(&path), Ok(()));
+        assert!(!path.with_extension("json.tmp").exists());
+        assert_eq!(Checkpoint::load(&path), Ok(checkpoint));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            Checkpoint::load(&path),
+            Err(CheckpointError::Io(_))
+        ));
+    }
+}
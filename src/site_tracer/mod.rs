@@ -1,123 +1,585 @@
-use tokio::task::JoinHandle;
-use tokio::time::sleep;
+use jiff::Timestamp;
+use politeness::{HostLimiter, HostPermit, SystemClock, Wait};
+use std::{future::Future, path::PathBuf, sync::Arc, time::Instant};
+use tokio::{
+    task::{self, JoinHandle},
+    time::sleep,
+};
 use trace::Trace;
 use tracing::Instrument;
+use url::Url;
 
+mod checkpoint;
+mod politeness;
 mod process_heap;
 mod trace;
 
-use crate::link_gatherer::LinkGatherer;
-use crate::link_map::{LinkMap, LinkMapValue};
+use process_heap::Process;
+
+pub use checkpoint::Checkpoint;
+pub use politeness::Politeness;
+
+use crate::canonicaliser::Canonicaliser;
+use crate::link_gatherer::{
+    Link, LinkAction, LinkGatherer, LinkPolicy, Login, PageRecord, RedirectHop, USER_AGENT,
+};
+use crate::link_map::{LinkMap, LinkMapValue, SkipReason};
+use crate::robots::{robots_url, Robots, RobotsCache};
+use crate::scope::Scope;
+use crate::sitemap::{self, Sitemap};
+use std::collections::{HashSet, VecDeque};
 use std::time::Duration;
+use thiserror::Error;
 
-fn format_link_as_url(link: &str, root: &str) -> String {
-    if link.starts_with("http") {
-        link.to_string()
-    } else {
-        let processed_url = if link.starts_with("/") {
-            link
-        } else {
-            &("/".to_string() + link)
-        };
-        root.to_string() + processed_url
-    }
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum TraceError {
+    #[error("unable to log in at {0}")]
+    Login(String),
 }
 
+/// Most sitemap files fetched when following sitemap indexes.
+const MAX_SITEMAPS: usize = 1000;
+
 pub struct SiteTracer<T: LinkGatherer + Clone + 'static> {
     pub link_getter: T,
     pub worker_pool_size: u16,
     pub initial_retry_delay_ms: u16,
     pub max_retries: u8,
+    /// Maximum number of links followed from the root
+    pub max_depth: Option<u16>,
+    /// Maximum number of pages visited
+    pub max_pages: Option<usize>,
+    pub canonicaliser: Canonicaliser,
+    pub scope: Scope,
+    pub politeness: Politeness,
+    pub respect_robots: bool,
+    /// Used to select the robots.txt rules which apply to this crawler
+    pub user_agent: String,
+    /// Also start from the pages listed in the site's sitemaps
+    pub seed_from_sitemaps: bool,
+    /// Check links outside the site respond successfully, without following their links
+    pub check_external: bool,
+    /// Which kinds of link found on a page are followed or only checked
+    pub link_policy: LinkPolicy,
+    /// Don't follow `rel="nofollow"` links or the links of pages whose meta robots or
+    /// X-Robots-Tag is nofollow
+    pub respect_nofollow: bool,
+    /// Periodically save the state of the trace to this file, and when interrupted with Ctrl-C,
+    /// so that it can be resumed
+    pub checkpoint: Option<PathBuf>,
+    /// Minimum time between checkpoints
+    pub checkpoint_interval: Duration,
+    /// The result of a previous crawl, whose pages are only downloaded again if their ETag or
+    /// Last-Modified shows they've changed
+    pub previous: Option<LinkMap>,
+    /// Log in with this form before the trace, and again whenever a page redirects to it
+    pub login: Option<Login>,
 }
 
-pub type WorkerResult = JoinHandle<(String, LinkMapValue, u8)>;
+/// The URL processed, its result, the next retry count and its depth
+pub type WorkerResult = (Url, LinkMapValue, u8, u16);
+
+/// The robots.txt rules of the hosts visited during a trace and the requests in flight to them.
+struct Hosts {
+    robots: RobotsCache,
+    limiter: HostLimiter,
+}
 
 impl<T: LinkGatherer + Clone + 'static> SiteTracer<T> {
+    pub fn new(link_getter: T) -> Self {
+        SiteTracer {
+            link_getter,
+            worker_pool_size: 100,
+            initial_retry_delay_ms: 250,
+            max_retries: 3,
+            max_depth: None,
+            max_pages: None,
+            canonicaliser: Canonicaliser::default(),
+            scope: Scope::default(),
+            politeness: Politeness::default(),
+            respect_robots: true,
+            user_agent: USER_AGENT.to_string(),
+            seed_from_sitemaps: false,
+            check_external: false,
+            link_policy: LinkPolicy::default(),
+            respect_nofollow: false,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(30),
+            previous: None,
+            login: None,
+        }
+    }
+
+    fn hosts(&self) -> Hosts {
+        Hosts {
+            robots: RobotsCache::new(),
+            limiter: HostLimiter::new(self.politeness.clone(), SystemClock),
+        }
+    }
+
+    /// The robots.txt rules for the URL's origin, fetched once per origin.
+    async fn load_robots(&self, hosts: &Hosts, url: &Url) -> Arc<Robots> {
+        hosts
+            .robots
+            .get_or_fetch(url, || async {
+                let Some(robots_url) = robots_url(url) else {
+                    return Robots::default();
+                };
+                tracing::info!("Fetching {}", robots_url);
+                let _permit = hosts.limiter.acquire_waiting(&robots_url, None).await;
+                let response = self.link_getter.clone().get_content(&robots_url).await;
+                let rules = Robots::from_response(response, &self.user_agent);
+                if let Some(delay) = rules.crawl_delay() {
+                    tracing::info!("{} requests a crawl delay of {:?}", robots_url, delay);
+                }
+                rules
+            })
+            .await
+    }
+
+    async fn is_allowed_by_robots(&self, hosts: &Hosts, url: &Url) -> bool {
+        !self.respect_robots || self.load_robots(hosts, url).await.is_allowed(url)
+    }
+
+    // the Crawl-delay of the URL's host, once its robots.txt has been fetched
+    fn crawl_delay(&self, hosts: &Hosts, url: &Url) -> Option<Duration> {
+        hosts.robots.get(url).and_then(|rules| rules.crawl_delay())
+    }
+
+    /// Gets the pages listed in the sitemaps named in the root's robots.txt, or in
+    /// `/sitemap.xml` when there are none, following any sitemap indexes.
+    async fn get_sitemap_urls(&self, hosts: &Hosts, root: &Url) -> Vec<Url> {
+        let mut queue: VecDeque<Url> = match self.load_robots(hosts, root).await.sitemaps() {
+            sitemaps if !sitemaps.is_empty() => sitemaps.iter().cloned().collect(),
+            _ => root.join("/sitemap.xml").into_iter().collect(),
+        };
+        let mut fetched = HashSet::new();
+        let mut urls = vec![];
+        while let Some(sitemap_url) = queue.pop_front() {
+            if fetched.len() >= MAX_SITEMAPS {
+                tracing::warn!("Stopped reading sitemaps after {}", MAX_SITEMAPS);
+                break;
+            }
+            if !fetched.insert(sitemap_url.clone()) {
+                continue;
+            }
+            tracing::info!("Fetching {}", sitemap_url);
+            let permit = hosts
+                .limiter
+                .acquire_waiting(&sitemap_url, self.crawl_delay(hosts, &sitemap_url))
+                .await;
+            let content = self
+                .link_getter
+                .clone()
+                .get_content_bytes(&sitemap_url)
+                .await;
+            drop(permit);
+            match content.map(|content| sitemap::parse(&content)) {
+                Ok(Ok(Sitemap::Index(sitemaps))) => queue.extend(sitemaps),
+                Ok(Ok(Sitemap::UrlSet(pages))) => urls.extend(pages),
+                Ok(Err(err)) => tracing::warn!("Unable to parse {} - {:?}", sitemap_url, err),
+                Err(err) => tracing::warn!("Unable to get {} - {:?}", sitemap_url, err),
+            }
+        }
+        urls
+    }
+
+    async fn log_in(&self) -> Result<(), TraceError> {
+        if let Some(login) = &self.login {
+            tracing::info!("Logging in at {}", login.url);
+            if let Err(err) = self.link_getter.clone().log_in(login).await {
+                tracing::error!("Unable to log in at {} - {:?}", login.url, err);
+                return Err(TraceError::Login(format!("{} - {:?}", login.url, err)));
+            }
+        }
+        Ok(())
+    }
+
+    // whether fetching the URL redirected to the login page, other than for the login page itself
+    fn is_session_expired(&self, url: &Url, location: &Url) -> bool {
+        self.login.as_ref().is_some_and(|login| {
+            let login = Login {
+                url: self.canonicaliser.canonicalise(&login.url),
+                ..login.clone()
+            };
+            login.is_login_page(location) && !login.is_login_page(url)
+        })
+    }
+
+    // whether any redirect the client followed to fetch the URL went to the login page
+    fn is_session_expired_following(&self, url: &Url, redirects: &[RedirectHop]) -> bool {
+        let mut from = url.clone();
+        redirects.iter().any(|hop| {
+            let location = self.canonicaliser.canonicalise(&hop.location);
+            let expired = self.is_session_expired(&from, &location);
+            from = location;
+            expired
+        })
+    }
+
+    /// Queues a newly found URL unless it is beyond the trace's limits or disallowed by robots.txt.
+    async fn visit(&self, trace: &mut Trace, hosts: &Hosts, url: &Url, depth: u16) {
+        if !trace.is_unvisited(url) {
+            return;
+        }
+        if self.exceeds_limits(trace, depth) {
+            trace.skip(url, SkipReason::Limit);
+        } else if self.is_allowed_by_robots(hosts, url).await {
+            trace.queue_to_process(url, 0, depth, &self.initial_retry_delay_ms);
+        } else {
+            trace.skip(url, SkipReason::Robots);
+        }
+    }
+
+    fn exceeds_limits(&self, trace: &Trace, depth: u16) -> bool {
+        self.max_depth.is_some_and(|max| depth > max)
+            || self.max_pages.is_some_and(|max| trace.pages() >= max)
+    }
+
     #[tracing::instrument(skip_all)]
-    fn worker(&self, url_: &str, root_: &str, retry: u8, delay: Option<Duration>) -> WorkerResult {
+    fn worker(
+        &self,
+        process: &Process,
+        root_: &Url,
+        permit: HostPermit,
+        previous: Option<PageRecord>,
+    ) -> impl Future<Output = WorkerResult> + Send + 'static {
         let mut link_getter = self.link_getter.clone();
-        let url = url_.to_string();
-        let root = root_.to_string();
-        tokio::spawn(
-            async move {
-                tracing::info!("Processing URL");
-                if let Some(dur) = delay {
-                    sleep(dur).await;
+        let url = process.url.clone();
+        let root = root_.clone();
+        let (retry, depth, check_only) = (process.retry, process.depth, process.check_only);
+        let canonicaliser = self.canonicaliser.clone();
+        let scope = self.scope.clone();
+        let check_external = self.check_external;
+        let link_policy = self.link_policy.clone();
+        async move {
+            // held until the worker finishes, or panics
+            let _permit = permit;
+            tracing::info!("Processing URL");
+            if check_only {
+                let value = LinkMapValue::External(link_getter.check_link(&url).await);
+                tracing::info!("Finished checking URL");
+                return (url, value, retry + 1, depth);
+            }
+            let record = match &previous {
+                Some(previous) => {
+                    link_getter
+                        .get_links_if_changed(&url, previous)
+                        .await
+                        .map(|record| {
+                            // an unchanged page keeps its links from the previous crawl
+                            record.unwrap_or_else(|| {
+                                tracing::info!("Unchanged since previous crawl");
+                                PageRecord {
+                                    unchanged: true,
+                                    crawled_at: Timestamp::now(),
+                                    ..previous.clone()
+                                }
+                            })
+                        })
                 }
-                let value = match link_getter.get_links(&url).await {
-                    Ok(mut links) => {
-                        links.sort();
-                        links.dedup();
-
-                        let filtered_links: Vec<String> = links
-                            .into_iter()
-                            .map(|link| format_link_as_url(&link, &root))
-                            .filter(|url| url.starts_with(&root))
-                            .collect();
-                        tracing::info!("Filtered to {} links", filtered_links.len());
-                        tracing::debug!("Filtered Links {:?}", filtered_links);
-                        LinkMapValue::Links(filtered_links)
-                    }
-                    Err(err) => LinkMapValue::Error(err),
-                };
-                tracing::info!("Finished processing URL");
-                (url.to_string(), value, retry + 1)
+                None => link_getter.get_links(&url).await,
+            };
+            let value = match record {
+                Ok(PageRecord {
+                    redirect: Some(redirect),
+                    ..
+                }) => LinkMapValue::Redirect(
+                    redirect.status,
+                    canonicaliser.canonicalise(&redirect.location),
+                ),
+                Ok(mut page) => {
+                    let is_http = |url: &Url| matches!(url.scheme(), "http" | "https");
+                    let mut links: Vec<(LinkAction, Link)> = page
+                        .links
+                        .iter()
+                        .map(|link| {
+                            let url = canonicaliser.canonicalise(&link.url);
+                            let link = Link { url, ..*link };
+                            (link_policy.action(link.kind), link)
+                        })
+                        .filter(|(action, link)| match action {
+                            LinkAction::Follow => {
+                                scope.contains(&root, &link.url)
+                                    || (check_external && is_http(&link.url))
+                            }
+                            LinkAction::Check => is_http(&link.url),
+                            LinkAction::Ignore => false,
+                        })
+                        .collect();
+                    // where a URL is linked more than once keep the link most acted on
+                    links.sort_by(|(a_action, a), (b_action, b)| {
+                        a.url.cmp(&b.url).then(b_action.cmp(a_action))
+                    });
+                    links.dedup_by(|(_, a), (_, b)| a.url == b.url);
+
+                    page.links = links.into_iter().map(|(_, link)| link).collect();
+                    tracing::info!("Filtered to {} links", page.links.len());
+                    tracing::debug!("Filtered Links {:?}", page.links);
+                    LinkMapValue::Page(page)
+                }
+                Err(err) => LinkMapValue::Error(err),
+            };
+            tracing::info!("Finished processing URL");
+            (url, value, retry + 1, depth)
+        }
+        .instrument(tracing::info_span!(
+            "thread",
+            url = process.url.to_string(),
+            retry = retry,
+            depth = depth
+        ))
+    }
+
+    // the page's record from the previous crawl, if it was fetched successfully then
+    fn previous_record(&self, url: &Url) -> Option<PageRecord> {
+        match self.previous.as_ref()?.map.get(url) {
+            Some(LinkMapValue::Page(page)) => Some(page.clone()),
+            _ => None,
+        }
+    }
+
+    /// Starts a worker for the process if its host allows a request now. Otherwise it's deferred
+    /// until one of the host's requests finishes, or rescheduled for when the host's delays
+    /// allow, without holding a worker in the meantime.
+    fn start_process(&self, trace: &mut Trace, hosts: &Hosts, process: Process, root: &Url) {
+        let crawl_delay = self.crawl_delay(hosts, &process.url);
+        match hosts.limiter.acquire(&process.url, crawl_delay) {
+            Ok(permit) => {
+                let worker =
+                    self.worker(&process, root, permit, self.previous_record(&process.url));
+                trace.push_processor(process, worker);
+            }
+            Err(Wait::Full) => trace.defer(process),
+            Err(Wait::For(wait)) => trace.reschedule(process, wait),
+        }
+    }
+
+    /// Starts workers for the processes which are due, while there are workers free.
+    fn start_processes(&self, trace: &mut Trace, hosts: &Hosts, root: &Url) {
+        while trace.has_process_capacity() {
+            if let Some(process) = trace.get_next_process() {
+                self.start_process(trace, hosts, process, root);
+            } else {
+                break;
             }
-            .instrument(tracing::info_span!(
-                "thread",
-                url = url_.to_string(),
-                retry = retry,
-                delay = format!("{:?}", delay)
-            )),
-        )
+        }
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn trace(&self, root: &str) -> LinkMap {
+    pub async fn trace(&self, root: &Url) -> Result<LinkMap, TraceError> {
         tracing::info!("Begining trace");
+        let root = &self.canonicaliser.canonicalise(root);
         let mut trace = Trace::new(root, self.worker_pool_size);
-        trace.push_processor(self.worker(root, root, 0, None));
+        let hosts = self.hosts();
+        self.log_in().await?;
+        if self.is_allowed_by_robots(&hosts, root).await {
+            let process = Process::new(root, 0, 0, &self.initial_retry_delay_ms);
+            self.start_process(&mut trace, &hosts, process, root);
+        } else {
+            trace.skip(root, SkipReason::Robots);
+        }
+        if self.seed_from_sitemaps {
+            for url in self.get_sitemap_urls(&hosts, root).await {
+                let url = self.canonicaliser.canonicalise(&url);
+                if self.scope.contains(root, &url) {
+                    trace.add_sitemap_url(&url);
+                    self.visit(&mut trace, &hosts, &url, 0).await;
+                }
+            }
+        }
+
+        Ok(self.run(trace, hosts).await)
+    }
+
+    /// Continues an interrupted trace from its checkpoint.
+    #[tracing::instrument(skip_all)]
+    pub async fn resume(&self, checkpoint: Checkpoint) -> Result<LinkMap, TraceError> {
+        tracing::info!("Resuming trace");
+        let trace = Trace::from_checkpoint(checkpoint, self.worker_pool_size);
+        self.log_in().await?;
+        Ok(self.run(trace, self.hosts()).await)
+    }
+
+    // writes a snapshot of the trace on the blocking pool so serialising a large link map
+    // doesn't hold up the workers
+    fn save_checkpoint(&self, trace: &Trace) -> Option<JoinHandle<()>> {
+        let path = self.checkpoint.clone()?;
+        let checkpoint = trace.to_checkpoint();
+        Some(task::spawn_blocking(move || match checkpoint.save(&path) {
+            Ok(()) => tracing::info!("Saved checkpoint to {}", path.display()),
+            Err(err) => {
+                tracing::error!(
+                    "Unable to save checkpoint to {} - {:?}",
+                    path.display(),
+                    err
+                )
+            }
+        }))
+    }
+
+    // logs in again when the session a request was sent in has expired, unless a request which
+    // found it expired already has, returning whether the request can be retried
+    async fn renew_session(&self, trace: &mut Trace, sent_in: u64) -> bool {
+        if sent_in == trace.session() {
+            let logged_in = self.log_in().await.is_ok();
+            if !logged_in {
+                tracing::error!(
+                    "Unable to renew session, recording expired pages without retrying"
+                );
+            }
+            trace.renew_session(logged_in);
+        }
+        !trace.is_logged_out()
+    }
+
+    // records the page and visits or checks its links
+    async fn add_page(
+        &self,
+        trace: &mut Trace,
+        hosts: &Hosts,
+        root: &Url,
+        url: Url,
+        mut page: PageRecord,
+        depth: u16,
+    ) {
+        // redirects the client followed are recorded as though fetched one by one, with the page
+        // under the URL they led to
+        let mut url = url;
+        for hop in std::mem::take(&mut page.redirects) {
+            let location = self.canonicaliser.canonicalise(&hop.location);
+            trace.add_result(&url, LinkMapValue::Redirect(hop.status, location.clone()));
+            trace.mark_seen(&location);
+            url = location;
+        }
+        trace.add_result(&url, LinkMapValue::Page(page.clone()));
+        for Link {
+            url: link,
+            kind,
+            nofollow,
+        } in page.links
+        {
+            let follow = self.link_policy.action(kind) == LinkAction::Follow;
+            let nofollow = self.respect_nofollow && (nofollow || page.nofollow);
+            if follow && self.scope.contains(root, &link) {
+                if !nofollow {
+                    self.visit(trace, hosts, &link, depth + 1).await;
+                } else if trace.is_unvisited(&link) {
+                    trace.skip(&link, SkipReason::Nofollow);
+                }
+            } else if trace.is_unvisited(&link) {
+                trace.queue_to_check(&link, depth + 1);
+            }
+        }
+    }
+
+    // resolves on Ctrl-C when checkpointing, as the trace can then be resumed
+    async fn interrupted(&self) {
+        if self.checkpoint.is_none() || tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
 
-        print!("\x1B[2J\x1B[H");
+    async fn run(&self, mut trace: Trace, hosts: Hosts) -> LinkMap {
+        let root = &trace.root().clone();
+        let mut last_checkpoint = Instant::now();
+        let mut saving: Option<JoinHandle<()>> = None;
+        let interrupted = self.interrupted();
+        tokio::pin!(interrupted);
 
-        while let Some(result) = trace.get_next_processor() {
-            print!("\x1B[f\x1B[0J");
-            println!("{}", trace);
+        eprint!("\x1B[2J\x1B[H");
 
-            match result.await {
-                Ok((url, result, retry)) => match result.clone() {
-                    LinkMapValue::Links(links) => {
+        loop {
+            self.start_processes(&mut trace, &hosts, root);
+            eprint!("\x1B[f\x1B[0J");
+            eprintln!("{}", trace);
+
+            let running = trace.has_running();
+            if !running && trace.next_scheduled().is_none() && !trace.undefer_all() {
+                break;
+            }
+            // with workers free nothing more can start until the next process is due
+            let until_due = trace
+                .next_scheduled()
+                .filter(|_| trace.has_process_capacity())
+                .map(|due| {
+                    Timestamp::now()
+                        .duration_until(due)
+                        .try_into()
+                        .unwrap_or_default()
+                });
+            let next = tokio::select! {
+                next = trace.get_next_result(), if running => next,
+                _ = sleep(until_due.unwrap_or_default()), if until_due.is_some() => continue,
+                _ = &mut interrupted => {
+                    tracing::info!("Interrupted trace");
+                    break;
+                }
+            };
+            let Some(((url, result, retry, depth), session)) = next else {
+                continue;
+            };
+            match result.clone() {
+                LinkMapValue::Page(page)
+                    if retry <= self.max_retries
+                        && self.is_session_expired_following(&url, &page.redirects) =>
+                {
+                    tracing::info!("Session expired fetching {}", url);
+                    if self.renew_session(&mut trace, session).await {
+                        trace.queue_to_process(&url, retry, depth, &self.initial_retry_delay_ms);
+                    } else {
+                        self.add_page(&mut trace, &hosts, root, url, page, depth)
+                            .await;
+                    }
+                }
+                LinkMapValue::Page(page) => {
+                    self.add_page(&mut trace, &hosts, root, url, page, depth)
+                        .await;
+                }
+                LinkMapValue::Redirect(_, location) if self.is_session_expired(&url, &location) => {
+                    tracing::info!("Session expired fetching {}", url);
+                    if retry <= self.max_retries && self.renew_session(&mut trace, session).await {
+                        trace.queue_to_process(&url, retry, depth, &self.initial_retry_delay_ms);
+                    } else {
                         trace.add_result(&url, result);
-                        for link in links {
-                            trace.queue_to_process(&link, 0, &self.initial_retry_delay_ms);
-                        }
                     }
-                    LinkMapValue::Error(_) => {
-                        if retry > self.max_retries {
-                            trace.add_result(&url, result);
-                        } else {
-                            trace.queue_to_process(&url, retry, &self.initial_retry_delay_ms);
-                        }
+                }
+                LinkMapValue::Redirect(_, location) => {
+                    trace.add_result(&url, result);
+                    // the target stands in for the page so is visited at the same depth
+                    if self.scope.contains(root, &location) {
+                        self.visit(&mut trace, &hosts, &location, depth).await;
+                    } else if self.check_external && trace.is_unvisited(&location) {
+                        trace.queue_to_check(&location, depth);
+                    }
+                }
+                LinkMapValue::Error(err) => {
+                    if retry > self.max_retries || !err.is_retryable() {
+                        trace.add_result(&url, result);
+                    } else {
+                        trace.queue_to_process(&url, retry, depth, &self.initial_retry_delay_ms);
                     }
-                },
-                _ => (),
-            }
-            while trace.has_process_capacity() {
-                if let Some(process) = trace.get_next_process() {
-                    trace.push_processor(self.worker(
-                        &process.url,
-                        root,
-                        process.retry,
-                        process.get_delay(),
-                    ));
-                } else {
-                    break;
                 }
+                LinkMapValue::Skipped(_) | LinkMapValue::External(_) => {
+                    trace.add_result(&url, result)
+                }
+            }
+            // skipped while the last checkpoint is still being written
+            let saved = saving.as_ref().is_none_or(JoinHandle::is_finished);
+            if saved && last_checkpoint.elapsed() >= self.checkpoint_interval {
+                saving = self.save_checkpoint(&trace);
+                last_checkpoint = Instant::now();
             }
         }
+        if let Some(saving) = saving {
+            let _ = saving.await;
+        }
+        if let Some(saving) = self.save_checkpoint(&trace) {
+            let _ = saving.await;
+        }
 
-        print!("\x1B[f\x1B[0J");
-        println!("{}", trace);
+        eprint!("\x1B[f\x1B[0J");
+        eprintln!("{}", trace);
         tracing::info!("Finished trace");
         trace.get_result()
     }
@@ -127,13 +589,24 @@ impl<T: LinkGatherer + Clone + 'static> SiteTracer<T> {
 mod tests {
     use std::{
         collections::{HashMap, VecDeque},
-        future::Future,
         sync::{Arc, Mutex},
     };
 
-    use crate::{link_gatherer::URLContentGetterError, link_map::LinkMapValue};
+    use wiremock::{
+        matchers::{body_string, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        canonicaliser::{QueryParams, TRACKING_PARAMS},
+        link_gatherer::{
+            CssExtractor, ExtractorRegistry, HttpClient, LinkKind, Page, URLContentGetterError,
+        },
+        link_map::{LinkMapValue, SkipReason},
+        test_support::to_url,
+    };
 
-    type Response = Result<Vec<String>, URLContentGetterError>;
+    type Response = Result<Vec<Url>, URLContentGetterError>;
     #[derive(Debug, Clone)]
     pub enum Responses {
         Always(Response),
@@ -142,352 +615,1850 @@ mod tests {
 
     #[derive(Clone)]
     pub struct MockLG {
-        link_map: Arc<Mutex<HashMap<String, Responses>>>,
+        link_map: Arc<Mutex<HashMap<Url, Responses>>>,
+        content: Arc<HashMap<Url, String>>,
+        content_requests: Arc<Mutex<Vec<Url>>>,
+        link_requests: Arc<Mutex<Vec<Url>>>,
     }
 
     impl MockLG {
-        pub fn new(link_map: HashMap<String, Responses>) -> Self {
+        pub fn new(link_map: HashMap<Url, Responses>) -> Self {
             MockLG {
                 link_map: Arc::new(Mutex::new(link_map)),
+                content: Arc::new(HashMap::new()),
+                content_requests: Arc::new(Mutex::new(vec![])),
+                link_requests: Arc::new(Mutex::new(vec![])),
+            }
+        }
+
+        pub fn with_content(self, content: HashMap<Url, String>) -> Self {
+            MockLG {
+                content: Arc::new(content),
+                ..self
             }
         }
     }
 
     impl LinkGatherer for MockLG {
-        fn get_links(
-            &mut self,
-            url: &str,
-        ) -> impl Future<Output = Result<Vec<String>, URLContentGetterError>> + Send {
-            async {
-                if let Some(val) = self.link_map.lock().unwrap().get_mut(url) {
-                    return match val {
-                        Responses::Always(resp) => match resp {
-                            Ok(links) => Ok(links.clone()),
+        async fn get_links(&mut self, url: &Url) -> Result<PageRecord, URLContentGetterError> {
+            self.link_requests.lock().unwrap().push(url.clone());
+            if let Some(val) = self.link_map.lock().unwrap().get_mut(url) {
+                return match val {
+                    Responses::Always(resp) => match resp {
+                        Ok(links) => Ok(links.clone().into()),
+                        Err(err) => Err(err.clone()),
+                    },
+                    Responses::Exhaustable(ex) => match ex.pop_front() {
+                        Some(resp) => match resp {
+                            Ok(links) => Ok(links.clone().into()),
                             Err(err) => Err(err.clone()),
                         },
-                        Responses::Exhaustable(ex) => match ex.pop_front() {
-                            Some(resp) => match resp {
-                                Ok(links) => Ok(links.clone()),
-                                Err(err) => Err(err.clone()),
-                            },
-                            _ => Ok(vec![]),
-                        },
-                    };
-                }
-                Ok(vec![])
+                        _ => Ok(PageRecord::default()),
+                    },
+                };
+            }
+            Ok(PageRecord::default())
+        }
+
+        async fn get_content(&mut self, url: &Url) -> Result<String, URLContentGetterError> {
+            self.content_requests.lock().unwrap().push(url.clone());
+            match self.content.get(url) {
+                Some(content) => Ok(content.clone()),
+                None => Err(URLContentGetterError::Request(404)),
             }
         }
+
+        async fn get_content_bytes(&mut self, url: &Url) -> Result<Vec<u8>, URLContentGetterError> {
+            self.get_content(url).await.map(String::into_bytes)
+        }
+
+        async fn check_link(&mut self, url: &Url) -> Result<u16, URLContentGetterError> {
+            self.get_links(url).await.map(|_| 200)
+        }
+
+        async fn log_in(&mut self, _login: &Login) -> Result<(), URLContentGetterError> {
+            Ok(())
+        }
     }
 
     use super::*;
 
     #[tokio::test]
     async fn site_tracer_happy_path() {
-        let root = "http://www.example.com";
+        let root = to_url("http://www.example.com");
 
         let mock_lg = MockLG::new(HashMap::from([
             (
-                "http://www.example.com".to_string(),
+                to_url("http://www.example.com"),
                 Responses::Always(Ok(vec![
-                    "http://www.example.com/two".to_string(),
-                    "http://www.example.com/three".to_string(),
-                    "http://www.bolt.example.com/three".to_string(),
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                    to_url("http://www.bolt.example.com/three"),
                 ])),
             ),
             (
-                "http://www.example.com/two".to_string(),
+                to_url("http://www.example.com/two"),
                 Responses::Always(Ok(vec![
-                    "http://www.example.com/four".to_string(),
-                    "http://www.google.com/six".to_string(),
-                    "http://www.example.com/six".to_string(),
+                    to_url("http://www.example.com/four"),
+                    to_url("http://www.google.com/six"),
+                    to_url("http://www.example.com/six"),
                 ])),
             ),
             (
-                "http://www.example.com/three".to_string(),
+                to_url("http://www.example.com/three"),
                 Responses::Always(Ok(vec![
-                    "http://www.example.com/two".to_string(),
-                    "http://www.example.com/five".to_string(),
-                    "http://www.example.com/seven".to_string(),
-                    "http://www.example.com/five".to_string(),
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/five"),
+                    to_url("http://www.example.com/seven"),
+                    to_url("http://www.example.com/five"),
                 ])),
             ),
         ]));
 
-        let mut expected = LinkMap::new(root.to_string());
+        let mut expected = LinkMap::new(root.clone());
         expected.add(
-            "http://www.example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://www.example.com/two".to_string(),
-                "http://www.example.com/three".to_string(),
-            ]),
+            to_url("http://www.example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                ]
+                .into(),
+            ),
         );
         expected.add(
-            "http://www.example.com/two".to_string(),
-            LinkMapValue::Links(vec![
-                "http://www.example.com/four".to_string(),
-                "http://www.example.com/six".to_string(),
-            ]),
+            to_url("http://www.example.com/two"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://www.example.com/four"),
+                    to_url("http://www.example.com/six"),
+                ]
+                .into(),
+            ),
         );
         expected.add(
-            "http://www.example.com/three".to_string(),
-            LinkMapValue::Links(vec![
-                "http://www.example.com/two".to_string(),
-                "http://www.example.com/five".to_string(),
-                "http://www.example.com/seven".to_string(),
-            ]),
+            to_url("http://www.example.com/three"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/five"),
+                    to_url("http://www.example.com/seven"),
+                ]
+                .into(),
+            ),
         );
 
         let page = SiteTracer {
-            link_getter: mock_lg,
             max_retries: 4,
             worker_pool_size: 10,
             initial_retry_delay_ms: 250,
+            ..SiteTracer::new(mock_lg)
         };
-        let link_map = page.trace(root).await;
+        let link_map = page.trace(&root).await.unwrap();
 
         for (key, expected) in expected.map {
             match expected {
-                LinkMapValue::Links(mut ex) => match link_map.map.get(&key).unwrap().clone() {
-                    LinkMapValue::Links(mut a) => {
-                        a.sort();
-                        ex.sort();
-                        assert_eq!(a, ex)
+                LinkMapValue::Page(PageRecord { links: mut ex, .. }) => {
+                    match link_map.map.get(&key).unwrap().clone() {
+                        LinkMapValue::Page(PageRecord { links: mut a, .. }) => {
+                            a.sort();
+                            ex.sort();
+                            assert_eq!(a, ex)
+                        }
+                        _ => panic!("Actual should have Page value at {}", key),
                     }
-                    _ => assert!(false, "Actual should have Links value at {}", key),
-                },
+                }
                 LinkMapValue::Error(ex) => match link_map.map.get(&key).unwrap().clone() {
                     LinkMapValue::Error(a) => {
                         assert_eq!(a, ex)
                     }
-                    _ => assert!(false, "Actual should have Error value at {}", key),
+                    _ => panic!("Actual should have Error value at {}", key),
                 },
+                other => assert_eq!(link_map.map.get(&key), Some(&other)),
             }
         }
     }
 
     #[tokio::test]
     async fn site_tracer_handles_relative_urls() {
-        let root = "http://www.example.com";
-
-        let mock_lg = MockLG::new(HashMap::from([(
-            "http://www.example.com".to_string(),
-            Responses::Always(Ok(vec!["/two".to_string(), "three".to_string()])),
-        )]));
+        let server = MockServer::start().await;
+        for (route, body) in [
+            (
+                "/",
+                r#"<a href="/two">two</a><a href="docs/guide/">guide</a>"#,
+            ),
+            (
+                "/docs/guide/",
+                r#"<a href="../intro">intro</a><a href="./setup?step=1">setup</a><a href="//other.example.com/x">x</a>"#,
+            ),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/html"))
+                .mount(&server)
+                .await;
+        }
+        let root = to_url(&server.uri());
 
-        let mut expected = LinkMap::new(root.to_string());
+        let mut expected = LinkMap::new(root.clone());
+        expected.add(
+            root.clone(),
+            LinkMapValue::Page(
+                vec![
+                    root.join("/two").unwrap(),
+                    root.join("/docs/guide/").unwrap(),
+                ]
+                .into(),
+            ),
+        );
         expected.add(
-            "http://www.example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://www.example.com/two".to_string(),
-                "http://www.example.com/three".to_string(),
-            ]),
+            root.join("/docs/guide/").unwrap(),
+            LinkMapValue::Page(
+                vec![
+                    root.join("/docs/intro").unwrap(),
+                    root.join("/docs/guide/setup?step=1").unwrap(),
+                ]
+                .into(),
+            ),
         );
 
         let page = SiteTracer {
-            link_getter: mock_lg,
             max_retries: 1,
             worker_pool_size: 10,
             initial_retry_delay_ms: 25,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
         };
-        let link_map = page.trace(root).await;
+        let link_map = page.trace(&root).await.unwrap();
 
         for (key, expected) in expected.map {
             match expected {
-                LinkMapValue::Links(mut ex) => match link_map.map.get(&key).unwrap().clone() {
-                    LinkMapValue::Links(mut a) => {
-                        a.sort();
-                        ex.sort();
-                        assert_eq!(a, ex)
+                LinkMapValue::Page(PageRecord { links: mut ex, .. }) => {
+                    match link_map.map.get(&key).unwrap().clone() {
+                        LinkMapValue::Page(PageRecord { links: mut a, .. }) => {
+                            a.sort();
+                            ex.sort();
+                            assert_eq!(a, ex)
+                        }
+                        _ => panic!("Actual should have Page value at {}", key),
                     }
-                    _ => assert!(false, "Actual should have Links value at {}", key),
-                },
+                }
                 LinkMapValue::Error(ex) => match link_map.map.get(&key).unwrap().clone() {
                     LinkMapValue::Error(a) => {
                         assert_eq!(a, ex)
                     }
-                    _ => assert!(false, "Actual should have Error value at {}", key),
+                    _ => panic!("Actual should have Error value at {}", key),
                 },
+                other => assert_eq!(link_map.map.get(&key), Some(&other)),
             }
         }
     }
 
     #[tokio::test]
     async fn site_tracer_unhappy_path() {
-        let root = "http://www.example.com";
+        let root = to_url("http://www.example.com");
 
         let mock_lg = MockLG::new(HashMap::from([
             (
-                "http://www.example.com".to_string(),
+                to_url("http://www.example.com"),
                 Responses::Always(Ok(vec![
-                    "http://www.example.com/two".to_string(),
-                    "http://www.example.com/three".to_string(),
-                    "http://www.bolt.example.com/three".to_string(),
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                    to_url("http://www.bolt.example.com/three"),
                 ])),
             ),
             (
-                "http://www.example.com/two".to_string(),
+                to_url("http://www.example.com/two"),
                 Responses::Always(Err(URLContentGetterError::Request(401))),
             ),
             (
-                "http://www.example.com/three".to_string(),
+                to_url("http://www.example.com/three"),
                 Responses::Always(Err(URLContentGetterError::Content("Oh No".to_string()))),
             ),
         ]));
 
-        let mut expected = LinkMap::new(root.to_string());
+        let mut expected = LinkMap::new(root.clone());
         expected.add(
-            "http://www.example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://www.example.com/two".to_string(),
-                "http://www.example.com/three".to_string(),
-            ]),
+            to_url("http://www.example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                ]
+                .into(),
+            ),
         );
         expected.add(
-            "http://www.example.com/two".to_string(),
+            to_url("http://www.example.com/two"),
             LinkMapValue::Error(URLContentGetterError::Request(401)),
         );
         expected.add(
-            "http://www.example.com/three".to_string(),
+            to_url("http://www.example.com/three"),
             LinkMapValue::Error(URLContentGetterError::Content("Oh No".to_string())),
         );
 
         let page = SiteTracer {
-            link_getter: mock_lg,
             max_retries: 1,
             worker_pool_size: 10,
             initial_retry_delay_ms: 25,
+            ..SiteTracer::new(mock_lg)
         };
-        let link_map = page.trace(root).await;
+        let link_map = page.trace(&root).await.unwrap();
 
         for (key, expected) in expected.map {
             match expected {
-                LinkMapValue::Links(mut ex) => match link_map.map.get(&key).unwrap().clone() {
-                    LinkMapValue::Links(mut a) => {
-                        a.sort();
-                        ex.sort();
-                        assert_eq!(a, ex)
+                LinkMapValue::Page(PageRecord { links: mut ex, .. }) => {
+                    match link_map.map.get(&key).unwrap().clone() {
+                        LinkMapValue::Page(PageRecord { links: mut a, .. }) => {
+                            a.sort();
+                            ex.sort();
+                            assert_eq!(a, ex)
+                        }
+                        _ => panic!("Actual should have Page value at {}", key),
                     }
-                    _ => assert!(false, "Actual should have Links value at {}", key),
-                },
+                }
                 LinkMapValue::Error(ex) => match link_map.map.get(&key).unwrap().clone() {
                     LinkMapValue::Error(a) => {
                         assert_eq!(a, ex)
                     }
-                    _ => assert!(false, "Actual should have Error value at {}", key),
+                    _ => panic!("Actual should have Error value at {}", key),
                 },
+                other => assert_eq!(link_map.map.get(&key), Some(&other)),
             }
         }
     }
 
     #[tokio::test]
     async fn site_tracer_when_retry_suceeds_returns_links() {
-        let root = "http://www.example.com";
+        let root = to_url("http://www.example.com");
 
         let mock_lg = MockLG::new(HashMap::from([(
-            "http://www.example.com".to_string(),
+            to_url("http://www.example.com"),
             Responses::Exhaustable(VecDeque::from([
-                Err(URLContentGetterError::Request(401)),
-                Err(URLContentGetterError::Content(
-                    "Mysteries abound".to_string(),
-                )),
-                Err(URLContentGetterError::Request(401)),
+                Err(URLContentGetterError::Request(503)),
+                Err(URLContentGetterError::Timeout),
+                Err(URLContentGetterError::Request(0)),
                 Ok(vec![
-                    "http://www.example.com/two".to_string(),
-                    "http://www.example.com/three".to_string(),
-                    "http://www.bolt.example.com/three".to_string(),
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                    to_url("http://www.bolt.example.com/three"),
                 ]),
             ])),
         )]));
 
-        let mut expected = LinkMap::new(root.to_string());
+        let mut expected = LinkMap::new(root.clone());
         expected.add(
-            "http://www.example.com".to_string(),
-            LinkMapValue::Links(vec![
-                "http://www.example.com/two".to_string(),
-                "http://www.example.com/three".to_string(),
-            ]),
+            to_url("http://www.example.com"),
+            LinkMapValue::Page(
+                vec![
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                ]
+                .into(),
+            ),
         );
 
         let page = SiteTracer {
-            link_getter: mock_lg,
             max_retries: 3,
             worker_pool_size: 10,
             initial_retry_delay_ms: 25,
+            ..SiteTracer::new(mock_lg)
         };
-        let link_map = page.trace(root).await;
+        let link_map = page.trace(&root).await.unwrap();
         for (key, expected) in expected.map {
             match expected {
-                LinkMapValue::Links(mut ex) => match link_map.map.get(&key).unwrap().clone() {
-                    LinkMapValue::Links(mut a) => {
-                        a.sort();
-                        ex.sort();
-                        assert_eq!(a, ex)
+                LinkMapValue::Page(PageRecord { links: mut ex, .. }) => {
+                    match link_map.map.get(&key).unwrap().clone() {
+                        LinkMapValue::Page(PageRecord { links: mut a, .. }) => {
+                            a.sort();
+                            ex.sort();
+                            assert_eq!(a, ex)
+                        }
+                        _ => panic!("Actual should have Page value at {}", key),
                     }
-                    _ => assert!(false, "Actual should have Links value at {}", key),
-                },
+                }
                 LinkMapValue::Error(ex) => match link_map.map.get(&key).unwrap().clone() {
                     LinkMapValue::Error(a) => {
                         assert_eq!(a, ex)
                     }
-                    _ => assert!(false, "Actual should have Error value at {}", key),
+                    _ => panic!("Actual should have Error value at {}", key),
                 },
+                other => assert_eq!(link_map.map.get(&key), Some(&other)),
             }
         }
     }
 
     #[tokio::test]
     async fn site_tracer_when_max_retries_exhausted_returns_error() {
-        let root = "http://www.example.com";
+        let root = to_url("http://www.example.com");
 
         let mock_lg = MockLG::new(HashMap::from([(
-            "http://www.example.com".to_string(),
+            to_url("http://www.example.com"),
             Responses::Exhaustable(VecDeque::from([
-                Err(URLContentGetterError::Request(401)),
-                Err(URLContentGetterError::Request(401)),
-                Err(URLContentGetterError::Content(
-                    "Mysteries abound".to_string(),
-                )),
+                Err(URLContentGetterError::Request(503)),
+                Err(URLContentGetterError::Request(502)),
+                Err(URLContentGetterError::Timeout),
                 Ok(vec![
-                    "http://www.example.com/two".to_string(),
-                    "http://www.example.com/three".to_string(),
-                    "http://www.bolt.example.com/three".to_string(),
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                    to_url("http://www.bolt.example.com/three"),
                 ]),
             ])),
         )]));
 
-        let mut expected = LinkMap::new(root.to_string());
+        let mut expected = LinkMap::new(root.clone());
         expected.add(
-            "http://www.example.com".to_string(),
-            LinkMapValue::Error(URLContentGetterError::Content(
-                "Mysteries abound".to_string(),
-            )),
+            to_url("http://www.example.com"),
+            LinkMapValue::Error(URLContentGetterError::Timeout),
         );
 
         let page = SiteTracer {
-            link_getter: mock_lg,
             max_retries: 2,
             worker_pool_size: 10,
             initial_retry_delay_ms: 25,
+            ..SiteTracer::new(mock_lg)
         };
-        let link_map = page.trace(root).await;
+        let link_map = page.trace(&root).await.unwrap();
 
         for (key, expected) in expected.map {
             match expected {
-                LinkMapValue::Links(mut ex) => match link_map.map.get(&key).unwrap().clone() {
-                    LinkMapValue::Links(mut a) => {
-                        a.sort();
-                        ex.sort();
-                        assert_eq!(a, ex)
+                LinkMapValue::Page(PageRecord { links: mut ex, .. }) => {
+                    match link_map.map.get(&key).unwrap().clone() {
+                        LinkMapValue::Page(PageRecord { links: mut a, .. }) => {
+                            a.sort();
+                            ex.sort();
+                            assert_eq!(a, ex)
+                        }
+                        _ => panic!("Actual should have Page value at {}", key),
                     }
-                    _ => assert!(false, "Actual should have Links value at {}", key),
-                },
+                }
                 LinkMapValue::Error(ex) => match link_map.map.get(&key).unwrap().clone() {
                     LinkMapValue::Error(a) => {
                         assert_eq!(a, ex)
                     }
-                    _ => assert!(false, "Actual should have Error value at {}", key),
+                    _ => panic!("Actual should have Error value at {}", key),
                 },
+                other => assert_eq!(link_map.map.get(&key), Some(&other)),
             }
         }
     }
+
+    #[tokio::test]
+    async fn site_tracer_does_not_retry_client_errors_or_bad_content() {
+        let root = to_url("http://www.example.com");
+        let too_large = URLContentGetterError::Content("body larger than 10 bytes".to_string());
+        let fails_once = |err: URLContentGetterError| {
+            Responses::Exhaustable(VecDeque::from([Err(err), Ok(vec![])]))
+        };
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                root.clone(),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/missing"),
+                    to_url("http://www.example.com/large"),
+                ])),
+            ),
+            (
+                to_url("http://www.example.com/missing"),
+                fails_once(URLContentGetterError::Request(404)),
+            ),
+            (
+                to_url("http://www.example.com/large"),
+                fails_once(too_large.clone()),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            max_retries: 3,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/missing")),
+            Some(&LinkMapValue::Error(URLContentGetterError::Request(404)))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/large")),
+            Some(&LinkMapValue::Error(too_large))
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_records_http_error_status_from_server() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<a href="/missing">missing</a><a href="/broken">broken</a>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/missing"))
+            .respond_with(
+                ResponseTemplate::new(404).set_body_raw(r#"<a href="/hidden">x</a>"#, "text/html"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/broken"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let page = SiteTracer {
+            max_retries: 1,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map.map.get(&root.join("/missing").unwrap()),
+            Some(&LinkMapValue::Error(URLContentGetterError::Request(404)))
+        );
+        assert_eq!(
+            link_map.map.get(&root.join("/broken").unwrap()),
+            Some(&LinkMapValue::Error(URLContentGetterError::Request(500)))
+        );
+        assert_eq!(link_map.map.get(&root.join("/hidden").unwrap()), None);
+        assert!(link_map
+            .to_tree(5)
+            .contains(&format!("{}missing - 😵 404", root)));
+    }
+
+    #[tokio::test]
+    async fn site_tracer_records_redirects() {
+        let server = MockServer::start().await;
+        for (route, status, location) in [
+            ("/old", 301, "/new"),
+            ("/loop", 302, "/loop/"),
+            ("/loop/", 302, "/loop"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(status).insert_header("location", location))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<a href="/old">old</a><a href="/loop">loop</a>"#,
+                "text/html",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/new"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"<a href="/">home</a>"#, "text/html"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+        let client = HttpClient::from(
+            reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap(),
+        );
+
+        let page = SiteTracer {
+            max_retries: 1,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(client))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let url = |path: &str| root.join(path).unwrap();
+        assert_eq!(
+            link_map.map.get(&url("/old")),
+            Some(&LinkMapValue::Redirect(301, url("/new")))
+        );
+        assert_eq!(
+            link_map.map.get(&url("/new")).map(LinkMapValue::links),
+            Some(vec![&root])
+        );
+        assert_eq!(
+            link_map.map.get(&url("/loop")),
+            Some(&LinkMapValue::Redirect(302, url("/loop/")))
+        );
+        assert_eq!(
+            link_map.map.get(&url("/loop/")),
+            Some(&LinkMapValue::Redirect(302, url("/loop")))
+        );
+        assert!(link_map
+            .to_tree(5)
+            .contains(&format!("{}loop - ↪️ 302 ⚠️ redirect loop", root)));
+    }
+
+    #[tokio::test]
+    async fn site_tracer_records_redirects_the_client_follows() {
+        let server = MockServer::start().await;
+        for (route, status, location) in [("/old", 301, "/moved"), ("/moved", 302, "/new")] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(status).insert_header("location", location))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        for (route, body) in [("/", r#"<a href="/old">old</a>"#), ("/new", "")] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/html"))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let root = to_url(&server.uri());
+
+        let page = SiteTracer {
+            max_retries: 1,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let url = |path: &str| root.join(path).unwrap();
+        assert_eq!(
+            link_map.map.get(&url("/old")),
+            Some(&LinkMapValue::Redirect(301, url("/moved")))
+        );
+        assert_eq!(
+            link_map.map.get(&url("/moved")),
+            Some(&LinkMapValue::Redirect(302, url("/new")))
+        );
+        assert!(matches!(
+            link_map.map.get(&url("/new")),
+            Some(LinkMapValue::Page(page)) if page.final_url == Some(url("/new"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn site_tracer_reports_urls_of_rewritten_origin() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<a href="/one">one</a><a href="/old">old</a>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/old"))
+            .respond_with(
+                ResponseTemplate::new(301)
+                    .insert_header("location", format!("{}/one", server.uri())),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/one"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("one", "text/html"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let root = to_url("https://www.example.com/");
+        let client = HttpClient {
+            rewrites: vec![format!("https://www.example.com={}", server.uri())
+                .parse()
+                .unwrap()],
+            ..HttpClient::from(
+                reqwest::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let page = SiteTracer {
+            max_retries: 0,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(client))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let url = |path: &str| root.join(path).unwrap();
+        assert!(link_map.map.keys().all(|key| key.origin() == root.origin()));
+        assert_eq!(
+            link_map.map.get(&root).map(LinkMapValue::links),
+            Some(vec![&url("/old"), &url("/one")])
+        );
+        assert_eq!(
+            link_map.map.get(&url("/old")),
+            Some(&LinkMapValue::Redirect(301, url("/one")))
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_follows_and_checks_links_by_kind() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(r#"<a href="/one">one</a><img src="/logo.png"><img src="/one"><script src="/app.js"></script>"#, "text/html"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/one"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"<img src="/missing.png">"#, "text/html"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/logo.png"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/logo.png"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let page = SiteTracer {
+            max_retries: 0,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            link_policy: LinkPolicy {
+                check: vec![LinkKind::Image],
+                ..LinkPolicy::default()
+            },
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let url = |path: &str| root.join(path).unwrap();
+        assert_eq!(
+            link_map.map.get(&root).map(LinkMapValue::links),
+            Some(vec![&url("/logo.png"), &url("/one")])
+        );
+        assert!(matches!(
+            link_map.map.get(&url("/one")),
+            Some(LinkMapValue::Page(_))
+        ));
+        assert_eq!(
+            link_map.map.get(&url("/logo.png")),
+            Some(&LinkMapValue::External(Ok(200)))
+        );
+        assert_eq!(
+            link_map.map.get(&url("/missing.png")),
+            Some(&LinkMapValue::External(Err(
+                URLContentGetterError::Request(404)
+            )))
+        );
+        assert_eq!(link_map.map.get(&url("/app.js")), None);
+    }
+
+    #[tokio::test]
+    async fn site_tracer_checks_stylesheets_by_default_and_gathers_their_urls_when_followed() {
+        let server = MockServer::start().await;
+        for (route, body, content_type) in [
+            (
+                "/",
+                r#"<link rel="stylesheet" href="/main.css"><img src="/img/hero.png"><a href="/one">one</a>"#,
+                "text/html",
+            ),
+            (
+                "/main.css",
+                r#"@import "theme.css"; body { background: url(/img/bg.png) }"#,
+                "text/css",
+            ),
+            (
+                "/theme.css",
+                "h1 { background: url(/img/logo.png) }",
+                "text/css",
+            ),
+            ("/img/hero.png", "", "image/png"),
+            ("/img/bg.png", "", "image/png"),
+            ("/img/logo.png", "", "image/png"),
+            ("/one", "", "text/html"),
+        ] {
+            Mock::given(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, content_type))
+                .mount(&server)
+                .await;
+        }
+        let root = to_url(&server.uri());
+        let url = |path: &str| root.join(path).unwrap();
+        let tracer = |link_policy| {
+            let mut extractors = ExtractorRegistry::default();
+            extractors.register(CssExtractor);
+            SiteTracer {
+                max_retries: 0,
+                worker_pool_size: 10,
+                initial_retry_delay_ms: 5,
+                link_policy,
+                ..SiteTracer::new(Page::new(HttpClient::default()).with_extractors(extractors))
+            }
+        };
+
+        let link_map = tracer(LinkPolicy::default()).trace(&root).await.unwrap();
+        for path in ["/main.css", "/img/hero.png"] {
+            assert_eq!(
+                link_map.map.get(&url(path)),
+                Some(&LinkMapValue::External(Ok(200))),
+                "{} should have been checked",
+                path
+            );
+        }
+        assert!(matches!(
+            link_map.map.get(&url("/one")),
+            Some(LinkMapValue::Page(_))
+        ));
+        assert_eq!(link_map.map.get(&url("/img/bg.png")), None);
+
+        let following = LinkPolicy {
+            follow: [LinkKind::Anchor, LinkKind::Link, LinkKind::Style].to_vec(),
+            ..LinkPolicy::default()
+        };
+        let link_map = tracer(following).trace(&root).await.unwrap();
+        assert_eq!(
+            link_map.map.get(&url("/main.css")).map(LinkMapValue::links),
+            Some(vec![&url("/img/bg.png"), &url("/theme.css")])
+        );
+        for path in ["/img/bg.png", "/img/logo.png"] {
+            assert!(
+                matches!(link_map.map.get(&url(path)), Some(LinkMapValue::Page(page)) if page.content_type.as_deref() == Some("image/png")),
+                "{} should have been fetched",
+                path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn site_tracer_respects_nofollow() {
+        let server = MockServer::start().await;
+        for (route, body) in [
+            (
+                "/",
+                r#"<a href="/one">one</a><a href="/paid" rel="sponsored nofollow">paid</a><a href="/later" rel="nofollow">later</a><a href="/four">four</a>"#,
+            ),
+            ("/four", r#"<a href="/later">later</a>"#),
+            ("/later", ""),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/html"))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/one"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-robots-tag", "nofollow")
+                    .set_body_raw(r#"<a href="/two">two</a>"#, "text/html"),
+            )
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let page = SiteTracer {
+            max_retries: 0,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            respect_nofollow: true,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let url = |path: &str| root.join(path).unwrap();
+        assert_eq!(
+            link_map.map.get(&url("/paid")),
+            Some(&LinkMapValue::Skipped(SkipReason::Nofollow))
+        );
+        assert_eq!(
+            link_map.map.get(&url("/two")),
+            Some(&LinkMapValue::Skipped(SkipReason::Nofollow))
+        );
+        assert!(matches!(
+            link_map.map.get(&url("/one")),
+            Some(LinkMapValue::Page(page)) if page.nofollow
+        ));
+        assert!(matches!(
+            link_map.map.get(&url("/later")),
+            Some(LinkMapValue::Page(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn site_tracer_records_page_metadata() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+                    .set_body_raw(r#"<title>Home</title><a href="/two">two</a>"#, "text/html"),
+            )
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let page = SiteTracer {
+            max_retries: 1,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let Some(LinkMapValue::Page(page)) = link_map.map.get(&root) else {
+            panic!("Actual should have Page value at {}", root);
+        };
+        assert_eq!(page.status, 200);
+        assert_eq!(page.content_type.as_deref(), Some("text/html"));
+        assert_eq!(page.title.as_deref(), Some("Home"));
+        assert_eq!(page.final_url.as_ref(), Some(&root));
+        assert_eq!(
+            page.last_modified,
+            Some("2015-10-21T07:28:00Z".parse().unwrap())
+        );
+        assert_eq!(
+            page.links,
+            PageRecord::from(vec![root.join("/two").unwrap()]).links
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_records_other_resources_without_following_them() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"<a href="/report.pdf">report</a>"#, "text/html"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/report.pdf"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"<a href="/hidden">hidden</a>"#, "application/pdf"),
+            )
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+        let pdf = root.join("/report.pdf").unwrap();
+
+        let page = SiteTracer {
+            max_retries: 1,
+            worker_pool_size: 10,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let Some(LinkMapValue::Page(page)) = link_map.map.get(&pdf) else {
+            panic!("Actual should have Page value at {}", pdf);
+        };
+        assert_eq!(page.content_type.as_deref(), Some("application/pdf"));
+        assert_eq!(page.content_length, Some(28));
+        assert_eq!(page.links, vec![]);
+        assert_eq!(link_map.map.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn site_tracer_reuses_links_of_unchanged_pages() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"v1\"")
+                    .set_body_raw(r#"<a href="/two">two</a>"#, "text/html"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/two"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("two", "text/html"))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let first = SiteTracer {
+            max_retries: 1,
+            initial_retry_delay_ms: 5,
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        }
+        .trace(&root)
+        .await
+        .unwrap();
+        let second = SiteTracer {
+            max_retries: 1,
+            initial_retry_delay_ms: 5,
+            previous: Some(first.clone()),
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        }
+        .trace(&root)
+        .await
+        .unwrap();
+
+        let Some(LinkMapValue::Page(page)) = second.map.get(&root) else {
+            panic!("Actual should have Page value at {}", root);
+        };
+        assert!(page.unchanged);
+        assert_eq!(page.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(
+            page.links,
+            PageRecord::from(vec![root.join("/two").unwrap()]).links
+        );
+        assert_eq!(second.map.len(), 2);
+        assert_eq!(
+            second.to_recrawl_summary(&first),
+            "Recrawl - 1 pages unchanged, 1 changed, 0 new\n"
+        );
+    }
+
+    async fn logs_in_before_and_when_session_expires(follow_redirects: Option<usize>) {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<form id="login" action="/session" method="post">
+                     <input type="hidden" name="csrf" value="t0k3n">
+                     <input name="username"><input type="password" name="password">
+                   </form>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/session"))
+            .and(body_string("csrf=t0k3n&username=admin&password=secret"))
+            .respond_with(
+                ResponseTemplate::new(303)
+                    .insert_header("set-cookie", "session=1; Path=/")
+                    .insert_header("location", "/"),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        // the session expires the first time the private page is fetched
+        Mock::given(method("GET"))
+            .and(path("/private"))
+            .respond_with(
+                ResponseTemplate::new(302).insert_header("location", "/login?next=/private"),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        for (route, body) in [("/", r#"<a href="/private">private</a>"#), ("/private", "")] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .and(header("cookie", "session=1"))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/html"))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", "/login"))
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+        let client = HttpClient {
+            follow_redirects,
+            ..HttpClient::from(
+                reqwest::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .cookie_store(true)
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let page = SiteTracer {
+            max_retries: 1,
+            initial_retry_delay_ms: 5,
+            respect_robots: false,
+            login: Some(Login {
+                url: root.join("/login").unwrap(),
+                form_selector: "form#login".to_string(),
+                fields: vec![
+                    ("username".to_string(), "admin".to_string()),
+                    ("password".to_string(), "secret".to_string()),
+                ],
+            }),
+            ..SiteTracer::new(Page::new(client))
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let private = root.join("/private").unwrap();
+        assert!(matches!(
+            link_map.map.get(&root),
+            Some(LinkMapValue::Page(_))
+        ));
+        assert!(matches!(
+            link_map.map.get(&private),
+            Some(LinkMapValue::Page(_))
+        ));
+        assert_eq!(link_map.map.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn site_tracer_logs_in_before_and_when_session_expires() {
+        logs_in_before_and_when_session_expires(None).await;
+    }
+
+    #[tokio::test]
+    async fn site_tracer_logs_in_when_followed_redirect_reaches_login_page() {
+        logs_in_before_and_when_session_expires(Some(10)).await;
+    }
+
+    // a server whose login form sets the given sessions in turn, then rejects logins
+    async fn login_server(sessions: &[&str]) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<form id="login" action="/session" method="post">
+                     <input name="username">
+                   </form>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        for session in sessions {
+            Mock::given(method("POST"))
+                .and(path("/session"))
+                .respond_with(
+                    ResponseTemplate::new(303)
+                        .insert_header("set-cookie", format!("session={}; Path=/", session))
+                        .insert_header("location", "/"),
+                )
+                .up_to_n_times(1)
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        server
+    }
+
+    fn logging_in(root: &Url) -> SiteTracer<Page<HttpClient>> {
+        SiteTracer {
+            max_retries: 3,
+            initial_retry_delay_ms: 5,
+            respect_robots: false,
+            login: Some(Login {
+                url: root.join("/login").unwrap(),
+                form_selector: "form#login".to_string(),
+                fields: vec![("username".to_string(), "admin".to_string())],
+            }),
+            ..SiteTracer::new(Page::new(HttpClient::from(
+                reqwest::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .cookie_store(true)
+                    .build()
+                    .unwrap(),
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn site_tracer_logs_in_once_when_pages_find_session_expired_together() {
+        let server = login_server(&["1", "2"]).await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<a href="/a">a</a><a href="/b">b</a><a href="/c">c</a>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("cookie", "session=2"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("", "text/html"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", "/login"))
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+
+        let link_map = logging_in(&root).trace(&root).await.unwrap();
+
+        for path in ["/a", "/b", "/c"] {
+            assert!(
+                matches!(
+                    link_map.map.get(&root.join(path).unwrap()),
+                    Some(LinkMapValue::Page(_))
+                ),
+                "{} should have been fetched in the new session",
+                path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn site_tracer_stops_retrying_expired_pages_when_login_fails() {
+        let server = login_server(&["1"]).await;
+        Mock::given(method("POST"))
+            .and(path("/session"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"<a href="/a">a</a><a href="/b">b</a>"#, "text/html"),
+            )
+            .mount(&server)
+            .await;
+        for route in ["/a", "/b"] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(302).insert_header("location", "/login"))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let root = to_url(&server.uri());
+
+        let link_map = logging_in(&root).trace(&root).await.unwrap();
+
+        let login = root.join("/login").unwrap();
+        for path in ["/a", "/b"] {
+            assert_eq!(
+                link_map.map.get(&root.join(path).unwrap()),
+                Some(&LinkMapValue::Redirect(302, login.clone()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn site_tracer_fails_when_initial_login_is_rejected() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<form id="login" action="/session" method="post">
+                     <input name="username"><input type="password" name="password">
+                   </form>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/session"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let root = to_url(&server.uri());
+        let login = root.join("/login").unwrap();
+
+        let page = SiteTracer {
+            respect_robots: false,
+            login: Some(Login {
+                url: login.clone(),
+                form_selector: "form#login".to_string(),
+                fields: vec![("username".to_string(), "admin".to_string())],
+            }),
+            ..SiteTracer::new(Page::new(HttpClient::default()))
+        };
+
+        assert_eq!(
+            page.trace(&root).await,
+            Err(TraceError::Login(format!(
+                "{} - {:?}",
+                login,
+                URLContentGetterError::Request(401)
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_dedupes_canonical_urls() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                to_url("http://www.example.com"),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/two#top"),
+                    to_url("http://www.example.com/two?utm_source=newsletter"),
+                    to_url("HTTP://WWW.EXAMPLE.COM:80/two#bottom"),
+                    to_url("http://www.example.com/three?b=2&a=1"),
+                ])),
+            ),
+            (
+                to_url("http://www.example.com/two"),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/#top"),
+                    to_url("http://www.example.com/three?a=1&b=2&sid=123"),
+                ])),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            canonicaliser: Canonicaliser {
+                query_params: QueryParams::Drop(
+                    TRACKING_PARAMS.iter().map(|p| p.to_string()).collect(),
+                ),
+                sort_query: true,
+                ..Canonicaliser::default()
+            },
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let mut keys: Vec<String> = link_map.map.keys().map(|k| k.to_string()).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "http://www.example.com/",
+                "http://www.example.com/three?a=1&b=2",
+                "http://www.example.com/two",
+            ]
+        );
+        assert_eq!(
+            link_map.map.get(&root),
+            Some(&LinkMapValue::Page(
+                vec![
+                    to_url("http://www.example.com/three?a=1&b=2"),
+                    to_url("http://www.example.com/two"),
+                ]
+                .into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_follows_links_within_scope() {
+        let root = to_url("http://www.example.com/docs/");
+
+        let mock_lg = MockLG::new(HashMap::from([(
+            to_url("http://www.example.com/docs/"),
+            Responses::Always(Ok(vec![
+                to_url("https://www.example.com/docs/secure"),
+                to_url("http://www.example.com/blog/"),
+                to_url("http://api.www.example.com/docs/ref"),
+                to_url("http://www.example.com.evil.net/docs/"),
+                to_url("http://static.example.net/docs/logo"),
+            ])),
+        )]));
+
+        let page = SiteTracer {
+            scope: Scope {
+                include_subdomains: true,
+                path_prefix: Some("/docs/".to_string()),
+                allowed_hosts: vec!["static.example.net".to_string()],
+                ..Scope::default()
+            },
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map.map.get(&root),
+            Some(&LinkMapValue::Page(
+                vec![
+                    to_url("http://api.www.example.com/docs/ref"),
+                    to_url("http://static.example.net/docs/logo"),
+                    to_url("https://www.example.com/docs/secure"),
+                ]
+                .into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_skips_urls_blocked_by_robots() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                to_url("http://www.example.com"),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/public"),
+                    to_url("http://www.example.com/private/one"),
+                    to_url("http://docs.example.com/private/two"),
+                ])),
+            ),
+            (
+                to_url("http://www.example.com/public"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/private/one")])),
+            ),
+            (
+                to_url("http://www.example.com/private/one"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/hidden")])),
+            ),
+        ]))
+        .with_content(HashMap::from([(
+            to_url("http://www.example.com/robots.txt"),
+            "User-agent: *\nDisallow: /private/\n".to_string(),
+        )]));
+
+        let page = SiteTracer {
+            scope: Scope {
+                include_subdomains: true,
+                allowed_hosts: vec!["docs.example.com".to_string()],
+                ..Scope::default()
+            },
+            ..SiteTracer::new(mock_lg.clone())
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map
+                .map
+                .get(&to_url("http://www.example.com/private/one")),
+            Some(&LinkMapValue::Skipped(SkipReason::Robots))
+        );
+        assert_eq!(
+            link_map
+                .map
+                .get(&to_url("http://docs.example.com/private/two")),
+            Some(&LinkMapValue::Page(vec![].into()))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/hidden")),
+            None
+        );
+        assert_eq!(
+            *mock_lg.content_requests.lock().unwrap(),
+            vec![
+                to_url("http://www.example.com/robots.txt"),
+                to_url("http://docs.example.com/robots.txt"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_ignores_robots_when_configured() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([(
+            to_url("http://www.example.com"),
+            Responses::Always(Ok(vec![to_url("http://www.example.com/private/one")])),
+        )]))
+        .with_content(HashMap::from([(
+            to_url("http://www.example.com/robots.txt"),
+            "User-agent: *\nDisallow: /\n".to_string(),
+        )]));
+
+        let page = SiteTracer {
+            respect_robots: false,
+            ..SiteTracer::new(mock_lg.clone())
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map.map.get(&root),
+            Some(&LinkMapValue::Page(
+                vec![to_url("http://www.example.com/private/one")].into()
+            ))
+        );
+        assert!(mock_lg.content_requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn site_tracer_waits_for_crawl_delay_between_requests() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([(
+            to_url("http://www.example.com"),
+            Responses::Always(Ok(vec![
+                to_url("http://www.example.com/one"),
+                to_url("http://www.example.com/two"),
+            ])),
+        )]))
+        .with_content(HashMap::from([(
+            to_url("http://www.example.com/robots.txt"),
+            "User-agent: *\nCrawl-delay: 1\n".to_string(),
+        )]));
+
+        let started = tokio::time::Instant::now();
+        let link_map = SiteTracer::new(mock_lg).trace(&root).await.unwrap();
+
+        assert_eq!(link_map.map.len(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(1900));
+    }
+
+    #[tokio::test]
+    async fn site_tracer_requests_other_hosts_while_one_waits() {
+        let root = to_url("http://a.example.com");
+        let mock_lg = MockLG::new(HashMap::from([(
+            root.clone(),
+            Responses::Always(Ok(vec![
+                to_url("http://a.example.com/two"),
+                to_url("http://b.example.com/one"),
+            ])),
+        )]));
+        let requests = mock_lg.link_requests.clone();
+
+        let page = SiteTracer {
+            worker_pool_size: 1,
+            check_external: true,
+            politeness: Politeness {
+                min_delay: Some(Duration::from_millis(300)),
+                ..Politeness::default()
+            },
+            ..SiteTracer::new(mock_lg)
+        };
+        let started = tokio::time::Instant::now();
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(link_map.map.len(), 3);
+        // robots.txt, the root then its link, each 300ms apart
+        assert!(started.elapsed() >= Duration::from_millis(600));
+        // the only worker isn't kept waiting for a.example.com's delay
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                root.clone(),
+                to_url("http://b.example.com/one"),
+                to_url("http://a.example.com/two"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn site_tracer_stops_at_max_depth() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                to_url("http://www.example.com"),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/one"),
+                    to_url("http://www.example.com/two"),
+                ])),
+            ),
+            (
+                to_url("http://www.example.com/one"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/one/deeper")])),
+            ),
+            (
+                to_url("http://www.example.com/one/deeper"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/too/deep")])),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            max_depth: Some(2),
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map
+                .map
+                .get(&to_url("http://www.example.com/one/deeper")),
+            Some(&LinkMapValue::Page(
+                vec![to_url("http://www.example.com/too/deep")].into()
+            ))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/too/deep")),
+            Some(&LinkMapValue::Skipped(SkipReason::Limit))
+        );
+        assert_eq!(link_map.map.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn site_tracer_stops_at_max_pages() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                to_url("http://www.example.com"),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/one"),
+                    to_url("http://www.example.com/two"),
+                    to_url("http://www.example.com/three"),
+                ])),
+            ),
+            (
+                to_url("http://www.example.com/one"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/four")])),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            max_pages: Some(2),
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let visited = link_map
+            .map
+            .values()
+            .filter(|v| matches!(v, LinkMapValue::Page(_)))
+            .count();
+        let skipped: Vec<&Url> = link_map
+            .map
+            .iter()
+            .filter(|(_, v)| **v == LinkMapValue::Skipped(SkipReason::Limit))
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(visited, 2);
+        assert_eq!(skipped.len(), 3);
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/one")),
+            Some(&LinkMapValue::Page(
+                vec![to_url("http://www.example.com/four")].into()
+            ))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/four")),
+            Some(&LinkMapValue::Skipped(SkipReason::Limit))
+        );
+    }
+
+    fn checkpoint_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn site_tracer_saves_checkpoint() {
+        let root = to_url("http://www.example.com");
+        let path = checkpoint_path("saves-checkpoint");
+
+        let mock_lg = MockLG::new(HashMap::from([(
+            root.clone(),
+            Responses::Always(Ok(vec![to_url("http://www.example.com/one")])),
+        )]));
+        let page = SiteTracer {
+            checkpoint: Some(path.clone()),
+            checkpoint_interval: Duration::ZERO,
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.link_map, link_map);
+        assert_eq!(
+            checkpoint.seen,
+            HashSet::from([root.clone(), to_url("http://www.example.com/one")])
+        );
+        assert!(checkpoint.pending.is_empty());
+        assert_eq!(checkpoint.pages, 2);
+    }
+
+    #[tokio::test]
+    async fn site_tracer_resumes_from_checkpoint() {
+        let root = to_url("http://www.example.com");
+        let url = |path: &str| root.join(path).unwrap();
+        let path = checkpoint_path("resumes-from-checkpoint");
+
+        let mut link_map = LinkMap::new(root.clone());
+        link_map.add(
+            root.clone(),
+            LinkMapValue::Page(vec![url("/one"), url("/two")].into()),
+        );
+        link_map.add(url("/one"), LinkMapValue::Page(vec![].into()));
+        let checkpoint = Checkpoint {
+            link_map,
+            seen: HashSet::from([root.clone(), url("/one"), url("/two")]),
+            pending: vec![Process::new(&url("/two"), 1, 1, &0)],
+            pages: 3,
+        };
+        // pages already traced aren't fetched again
+        let mock_lg = MockLG::new(HashMap::from([
+            (root.clone(), Responses::Always(Ok(vec![url("/other")]))),
+            (
+                url("/two"),
+                Responses::Exhaustable(VecDeque::from([
+                    Err(URLContentGetterError::Request(500)),
+                    Ok(vec![url("/three")]),
+                ])),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            max_retries: 2,
+            initial_retry_delay_ms: 5,
+            checkpoint: Some(path.clone()),
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.resume(checkpoint).await.unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            link_map.map.get(&url("/two")),
+            Some(&LinkMapValue::Page(vec![url("/three")].into()))
+        );
+        assert_eq!(
+            link_map.map.get(&url("/three")),
+            Some(&LinkMapValue::Page(vec![].into()))
+        );
+        assert_eq!(link_map.map.get(&url("/other")), None);
+        assert_eq!(link_map.map.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn site_tracer_seeds_from_sitemaps() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                to_url("http://www.example.com"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/linked")])),
+            ),
+            (
+                to_url("http://www.example.com/orphan"),
+                Responses::Always(Ok(vec![to_url("http://www.example.com/hidden")])),
+            ),
+            (
+                to_url("http://www.example.com/gone"),
+                Responses::Always(Err(URLContentGetterError::Request(404))),
+            ),
+        ]))
+        .with_content(HashMap::from([
+            (
+                to_url("http://www.example.com/robots.txt"),
+                "Sitemap: http://www.example.com/sitemap-index.xml\n".to_string(),
+            ),
+            (
+                to_url("http://www.example.com/sitemap-index.xml"),
+                r#"<sitemapindex><sitemap><loc>http://www.example.com/pages.xml</loc></sitemap></sitemapindex>"#
+                    .to_string(),
+            ),
+            (
+                to_url("http://www.example.com/pages.xml"),
+                r#"<urlset>
+  <url><loc>http://www.example.com/</loc></url>
+  <url><loc>http://www.example.com/orphan#top</loc></url>
+  <url><loc>http://www.example.com/gone</loc></url>
+  <url><loc>http://other.example.com/</loc></url>
+</urlset>"#
+                    .to_string(),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            max_retries: 0,
+            initial_retry_delay_ms: 5,
+            seed_from_sitemaps: true,
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map.sitemap,
+            HashSet::from([
+                to_url("http://www.example.com/"),
+                to_url("http://www.example.com/orphan"),
+                to_url("http://www.example.com/gone"),
+            ])
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/hidden")),
+            Some(&LinkMapValue::Page(vec![].into()))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://www.example.com/gone")),
+            Some(&LinkMapValue::Error(URLContentGetterError::Request(404)))
+        );
+        assert_eq!(link_map.map.get(&to_url("http://other.example.com/")), None);
+
+        let report = link_map.to_coverage_report();
+        assert!(report.contains("not reachable by links (2)\n  http://www.example.com/gone\n  http://www.example.com/orphan\n"));
+        assert!(report.contains("missing from sitemap (1)\n  http://www.example.com/linked\n"));
+    }
+
+    #[tokio::test]
+    async fn site_tracer_checks_external_links_without_following_them() {
+        let root = to_url("http://www.example.com");
+
+        let mock_lg = MockLG::new(HashMap::from([
+            (
+                to_url("http://www.example.com"),
+                Responses::Always(Ok(vec![
+                    to_url("http://www.example.com/one"),
+                    to_url("http://other.com/fine"),
+                    to_url("mailto:someone@example.com"),
+                ])),
+            ),
+            (
+                to_url("http://www.example.com/one"),
+                Responses::Always(Ok(vec![
+                    to_url("http://other.com/fine"),
+                    to_url("http://other.com/gone"),
+                ])),
+            ),
+            (
+                to_url("http://other.com/fine"),
+                Responses::Always(Ok(vec![to_url("http://other.com/deeper")])),
+            ),
+            (
+                to_url("http://other.com/gone"),
+                Responses::Always(Err(URLContentGetterError::Request(404))),
+            ),
+        ]));
+
+        let page = SiteTracer {
+            check_external: true,
+            ..SiteTracer::new(mock_lg)
+        };
+        let link_map = page.trace(&root).await.unwrap();
+
+        assert_eq!(
+            link_map.map.get(&root),
+            Some(&LinkMapValue::Page(
+                vec![
+                    to_url("http://other.com/fine"),
+                    to_url("http://www.example.com/one"),
+                ]
+                .into()
+            ))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://other.com/fine")),
+            Some(&LinkMapValue::External(Ok(200)))
+        );
+        assert_eq!(
+            link_map.map.get(&to_url("http://other.com/gone")),
+            Some(&LinkMapValue::External(Err(
+                URLContentGetterError::Request(404)
+            )))
+        );
+        assert_eq!(link_map.map.get(&to_url("http://other.com/deeper")), None);
+        assert_eq!(link_map.map.len(), 4);
+    }
 }
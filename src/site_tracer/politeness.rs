@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use jiff::{SignedDuration, Timestamp};
+use tokio::{sync::Notify, time::sleep};
+use url::Url;
+
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+}
+
+/// Limits on how hard any single host is hit during a trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Politeness {
+    pub max_in_flight_per_host: Option<u16>,
+    /// Minimum time between the start of consecutive requests to a host
+    pub min_delay: Option<Duration>,
+    /// Sustained request rate per host, allowing bursts of up to one second's worth
+    pub requests_per_sec: Option<f64>,
+    /// Use a host's robots.txt Crawl-delay when it is longer than `min_delay`
+    pub respect_crawl_delay: bool,
+}
+
+impl Default for Politeness {
+    fn default() -> Self {
+        Politeness {
+            max_in_flight_per_host: Some(8),
+            min_delay: None,
+            requests_per_sec: None,
+            respect_crawl_delay: true,
+        }
+    }
+}
+
+struct HostState {
+    in_flight: u16,
+    next_start: Timestamp,
+    tokens: f64,
+    refilled_at: Timestamp,
+}
+
+type Hosts = Arc<Mutex<HashMap<String, HostState>>>;
+
+/// Why a request to a host can't start yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wait {
+    /// The host has the most requests in flight it's allowed, until one of them finishes
+    Full,
+    /// The host's next request can start after this long
+    For(Duration),
+}
+
+/// Schedules requests per host according to a `Politeness` policy. Clones share the hosts'
+/// state.
+#[derive(Clone)]
+pub struct HostLimiter<C: Clock = SystemClock> {
+    policy: Politeness,
+    clock: C,
+    hosts: Hosts,
+    released: Arc<Notify>,
+}
+
+/// A request in flight to a host, which makes way for the host's next request when dropped,
+/// including when the task sending it panics.
+pub struct HostPermit {
+    hosts: Hosts,
+    host: String,
+    released: Arc<Notify>,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        if let Some(state) = self.hosts.lock().unwrap().get_mut(&self.host) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+        self.released.notify_waiters();
+    }
+}
+
+/// The key requests are limited by, which is the URL's host.
+pub fn host_key(url: &Url) -> String {
+    url.host_str().unwrap_or("").to_string()
+}
+
+fn add(timestamp: Timestamp, duration: Duration) -> Timestamp {
+    timestamp
+        .checked_add(SignedDuration::try_from(duration).unwrap_or(SignedDuration::MAX))
+        .unwrap_or(Timestamp::MAX)
+}
+
+fn seconds_between(from: Timestamp, to: Timestamp) -> f64 {
+    from.duration_until(to).as_secs_f64()
+}
+
+impl<C: Clock> HostLimiter<C> {
+    pub fn new(policy: Politeness, clock: C) -> Self {
+        HostLimiter {
+            policy,
+            clock,
+            hosts: Arc::default(),
+            released: Arc::default(),
+        }
+    }
+
+    /// Starts a request to the URL's host if the policy allows one now, otherwise returns how
+    /// long until it might.
+    pub fn acquire(&self, url: &Url, crawl_delay: Option<Duration>) -> Result<HostPermit, Wait> {
+        let now = self.clock.now();
+        let requests_per_sec = self
+            .policy
+            .requests_per_sec
+            .filter(|rps| rps.is_finite() && *rps > 0.0);
+        let burst = requests_per_sec.map(|rps| rps.max(1.0));
+        let host = host_key(url);
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = hosts.entry(host.clone()).or_insert(HostState {
+            in_flight: 0,
+            next_start: now,
+            tokens: burst.unwrap_or(0.0),
+            refilled_at: now,
+        });
+
+        if let Some(max) = self.policy.max_in_flight_per_host {
+            if state.in_flight >= max {
+                return Err(Wait::Full);
+            }
+        }
+
+        let mut start = state.next_start.max(now);
+        let mut tokens = state.tokens;
+        if let (Some(rps), Some(burst)) = (requests_per_sec, burst) {
+            tokens = (tokens + seconds_between(state.refilled_at, now) * rps).min(burst);
+            if tokens < 1.0 {
+                start = start.max(add(now, Duration::from_secs_f64((1.0 - tokens) / rps)));
+            }
+        }
+        if start > now {
+            return Err(Wait::For(
+                now.duration_until(start).try_into().unwrap_or_default(),
+            ));
+        }
+
+        if requests_per_sec.is_some() {
+            state.tokens = tokens - 1.0;
+            state.refilled_at = now;
+        }
+        let interval = match (self.policy.respect_crawl_delay, crawl_delay) {
+            (true, Some(crawl_delay)) => self.policy.min_delay.unwrap_or_default().max(crawl_delay),
+            _ => self.policy.min_delay.unwrap_or_default(),
+        };
+        state.next_start = add(now, interval);
+        state.in_flight += 1;
+
+        Ok(HostPermit {
+            hosts: self.hosts.clone(),
+            host,
+            released: self.released.clone(),
+        })
+    }
+
+    /// Waits until the policy allows a request to the URL's host, then starts it.
+    pub async fn acquire_waiting(&self, url: &Url, crawl_delay: Option<Duration>) -> HostPermit {
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            // registered before trying so a release in between isn't missed
+            released.as_mut().enable();
+            match self.acquire(url, crawl_delay) {
+                Ok(permit) => return permit,
+                Err(Wait::For(wait)) => sleep(wait).await,
+                Err(Wait::Full) => released.await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct FakeClock(Rc<Cell<Timestamp>>);
+
+    impl FakeClock {
+        fn new() -> Self {
+            FakeClock(Rc::new(Cell::new(Timestamp::UNIX_EPOCH)))
+        }
+
+        fn advance(&self, ms: u64) {
+            self.0.set(add(self.0.get(), Duration::from_millis(ms)));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Timestamp {
+            self.0.get()
+        }
+    }
+
+    fn ms(ms: u64) -> Option<Wait> {
+        Some(Wait::For(Duration::from_millis(ms)))
+    }
+
+    // how long the request has to wait, if it can't start now
+    fn wait(result: Result<HostPermit, Wait>) -> Option<Wait> {
+        result.err()
+    }
+
+    fn unlimited() -> Politeness {
+        Politeness {
+            max_in_flight_per_host: None,
+            min_delay: None,
+            requests_per_sec: None,
+            respect_crawl_delay: true,
+        }
+    }
+
+    #[test]
+    fn caps_in_flight_requests_per_host() {
+        let limiter = HostLimiter::new(
+            Politeness {
+                max_in_flight_per_host: Some(2),
+                ..unlimited()
+            },
+            FakeClock::new(),
+        );
+        let one = to_url("http://example.com/one");
+        let other = to_url("http://other.com/one");
+
+        let first = limiter.acquire(&one, None);
+        assert!(first.is_ok());
+        let _second = limiter.acquire(&one, None);
+        assert_eq!(wait(limiter.acquire(&one, None)), Some(Wait::Full));
+        assert_eq!(wait(limiter.acquire(&other, None)), None);
+
+        drop(first);
+        assert_eq!(wait(limiter.acquire(&one, None)), None);
+    }
+
+    #[tokio::test]
+    async fn releases_host_when_request_task_panics() {
+        let limiter = HostLimiter::new(
+            Politeness {
+                max_in_flight_per_host: Some(1),
+                ..unlimited()
+            },
+            SystemClock,
+        );
+        let url = to_url("http://example.com/one");
+        let permit = limiter.acquire(&url, None).ok().unwrap();
+        assert_eq!(wait(limiter.acquire(&url, None)), Some(Wait::Full));
+
+        let task = tokio::spawn(async move {
+            let _permit = permit;
+            panic!("worker failed");
+        });
+        assert!(task.await.unwrap_err().is_panic());
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_request_to_finish_when_host_is_full() {
+        let limiter = HostLimiter::new(
+            Politeness {
+                max_in_flight_per_host: Some(1),
+                ..unlimited()
+            },
+            SystemClock,
+        );
+        let url = to_url("http://example.com/one");
+        let permit = limiter.acquire(&url, None).ok().unwrap();
+
+        let waiting = tokio::spawn({
+            let (limiter, url) = (limiter.clone(), url.clone());
+            async move { limiter.acquire_waiting(&url, None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        drop(permit);
+        let _permit = waiting.await.unwrap();
+        assert_eq!(wait(limiter.acquire(&url, None)), Some(Wait::Full));
+    }
+
+    #[test]
+    fn spaces_requests_by_min_delay() {
+        let clock = FakeClock::new();
+        let limiter = HostLimiter::new(
+            Politeness {
+                min_delay: Some(Duration::from_millis(500)),
+                ..unlimited()
+            },
+            clock.clone(),
+        );
+        let url = to_url("http://example.com/one");
+
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), ms(500));
+        clock.advance(200);
+        assert_eq!(wait(limiter.acquire(&url, None)), ms(300));
+        clock.advance(300);
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), ms(500));
+        clock.advance(5000);
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(
+            wait(limiter.acquire(&to_url("http://other.com"), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn honours_crawl_delay_when_longer_than_min_delay() {
+        let clock = FakeClock::new();
+        let limiter = HostLimiter::new(
+            Politeness {
+                min_delay: Some(Duration::from_millis(500)),
+                ..unlimited()
+            },
+            clock.clone(),
+        );
+        let url = to_url("http://example.com/one");
+        let two_secs = Some(Duration::from_secs(2));
+        let short = Some(Duration::from_millis(100));
+        assert_eq!(wait(limiter.acquire(&url, two_secs)), None);
+        assert_eq!(wait(limiter.acquire(&url, two_secs)), ms(2000));
+        clock.advance(2000);
+        assert_eq!(wait(limiter.acquire(&url, short)), None);
+        assert_eq!(wait(limiter.acquire(&url, short)), ms(500));
+
+        let ignoring = HostLimiter::new(
+            Politeness {
+                respect_crawl_delay: false,
+                ..unlimited()
+            },
+            FakeClock::new(),
+        );
+        assert_eq!(wait(ignoring.acquire(&url, two_secs)), None);
+        assert_eq!(wait(ignoring.acquire(&url, two_secs)), None);
+    }
+
+    #[test]
+    fn limits_rate_with_token_bucket() {
+        let clock = FakeClock::new();
+        let limiter = HostLimiter::new(
+            Politeness {
+                requests_per_sec: Some(2.0),
+                ..unlimited()
+            },
+            clock.clone(),
+        );
+        let url = to_url("http://example.com/one");
+
+        // a full bucket allows a burst of two then one request every 500ms
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), ms(500));
+        clock.advance(500);
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), ms(500));
+
+        // idle time refills the bucket up to the burst size only
+        clock.advance(10_000);
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), None);
+        assert_eq!(wait(limiter.acquire(&url, None)), ms(500));
+    }
+}
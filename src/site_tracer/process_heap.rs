@@ -1,42 +1,37 @@
 use std::{cmp::Ordering, collections::BinaryHeap, time::Duration};
 
 use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use url::Url;
 
-#[derive(Eq, PartialOrd)]
+#[derive(Debug, Eq, Clone, Serialize, Deserialize)]
 pub struct Process {
-    pub url: String,
+    pub url: Url,
     pub timestamp: Timestamp,
     pub retry: u8,
+    /// Number of links followed from the root to reach this URL
+    pub depth: u16,
+    /// Only check the URL responds rather than gathering its links
+    pub check_only: bool,
 }
 
 impl Process {
-    pub fn new(url: &str, retry: u8, base_delay_ms: &u16) -> Self {
+    pub fn new(url: &Url, retry: u8, depth: u16, base_delay_ms: &u16) -> Self {
         let timestamp = if retry == 0 {
             Timestamp::now()
         } else {
             Timestamp::now()
                 .checked_add(Duration::from_millis(
-                    *base_delay_ms as u64 * (2 as u64).pow((retry) as u32),
+                    *base_delay_ms as u64 * 2_u64.pow((retry) as u32),
                 ))
                 .unwrap()
         };
         Process {
-            url: url.to_string(),
+            url: url.clone(),
             retry,
+            depth,
             timestamp,
-        }
-    }
-
-    pub fn get_delay(&self) -> Option<Duration> {
-        match Duration::try_from(Timestamp::now().until(self.timestamp).unwrap()) {
-            Ok(wait_dur) => {
-                if wait_dur > Duration::from_millis(0) {
-                    Some(wait_dur)
-                } else {
-                    None
-                }
-            }
-            Err(_) => None,
+            check_only: false,
         }
     }
 }
@@ -47,9 +42,16 @@ impl PartialEq for Process {
     }
 }
 
+impl PartialOrd for Process {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// reversed so the heap yields the earliest scheduled process first
 impl Ord for Process {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.timestamp.cmp(&other.timestamp)
+        other.timestamp.cmp(&self.timestamp)
     }
 }
 
@@ -68,7 +70,53 @@ impl ProcessHeap {
         self.0.pop()
     }
 
+    /// The earliest scheduled process.
+    pub fn peek(&self) -> Option<&Process> {
+        self.0.peek()
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Process> {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    fn process(path: &str, retry: u8) -> Process {
+        Process::new(
+            &to_url("http://www.example.com").join(path).unwrap(),
+            retry,
+            0,
+            &1000,
+        )
+    }
+
+    #[test]
+    fn pops_earliest_scheduled_first() {
+        let mut heap = ProcessHeap::new();
+        let first = process("/first", 0);
+        let second = Process {
+            timestamp: first
+                .timestamp
+                .checked_add(Duration::from_millis(1))
+                .unwrap(),
+            ..process("/second", 0)
+        };
+        heap.push(process("/retry", 2));
+        heap.push(second);
+        heap.push(first);
+
+        let popped: Vec<String> = std::iter::from_fn(|| heap.pop())
+            .map(|process| process.url.path().to_string())
+            .collect();
+        assert_eq!(popped, vec!["/first", "/second", "/retry"]);
+    }
 }
@@ -1,65 +1,292 @@
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     fmt::{Display, Formatter, Result},
+    future::Future,
 };
 
-use crate::link_map::{LinkMap, LinkMapValue};
+use jiff::Timestamp;
+use tokio::task::{self, JoinSet};
+use url::Url;
+
+use crate::link_map::{LinkMap, LinkMapValue, SkipReason};
 
 use super::{
+    checkpoint::Checkpoint,
+    politeness::host_key,
     process_heap::{Process, ProcessHeap},
     WorkerResult,
 };
 
 pub struct Trace {
     link_map: LinkMap,
-    seen: HashSet<String>,
+    seen: HashSet<Url>,
     heap: ProcessHeap,
-    processors: VecDeque<WorkerResult>,
+    // processes waiting on their host having capacity, keyed by host
+    deferred: HashMap<String, VecDeque<Process>>,
+    processors: JoinSet<WorkerResult>,
+    // processes with a running worker, kept so they can be checkpointed
+    in_flight: HashMap<Url, Process>,
+    // the URL each running worker is processing and the login session its request was sent in
+    tasks: HashMap<task::Id, (Url, u64)>,
+    worker_pool_size: usize,
+    // number of URLs queued to be fetched, excluding retries
+    pages: usize,
+    // counts logins so that requests sent before the latest one can be told apart
+    session: u64,
+    // whether the latest login failed
+    logged_out: bool,
 }
 
 impl Trace {
-    pub fn new(root: &str, worker_pool_size: u16) -> Self {
+    pub fn new(root: &Url, worker_pool_size: u16) -> Self {
         Trace {
-            link_map: LinkMap::new(root.to_string()),
-            seen: HashSet::from([root.to_string()]),
+            link_map: LinkMap::new(root.clone()),
+            seen: HashSet::from([root.clone()]),
             heap: ProcessHeap::new(),
-            processors: VecDeque::with_capacity(worker_pool_size as usize),
+            deferred: HashMap::new(),
+            processors: JoinSet::new(),
+            in_flight: HashMap::new(),
+            tasks: HashMap::new(),
+            worker_pool_size: worker_pool_size as usize,
+            pages: 1,
+            session: 0,
+            logged_out: false,
+        }
+    }
+
+    /// Continues a trace from a checkpoint, queueing every process which was pending.
+    pub fn from_checkpoint(checkpoint: Checkpoint, worker_pool_size: u16) -> Self {
+        let mut heap = ProcessHeap::new();
+        for process in checkpoint.pending {
+            heap.push(process);
+        }
+        Trace {
+            link_map: checkpoint.link_map,
+            seen: checkpoint.seen,
+            heap,
+            deferred: HashMap::new(),
+            processors: JoinSet::new(),
+            in_flight: HashMap::new(),
+            tasks: HashMap::new(),
+            worker_pool_size: worker_pool_size as usize,
+            pages: checkpoint.pages,
+            session: 0,
+            logged_out: false,
+        }
+    }
+
+    /// The state of the trace, with processes which are running or waiting on their host
+    /// counted as pending.
+    pub fn to_checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            link_map: self.link_map.clone(),
+            seen: self.seen.clone(),
+            pending: self
+                .heap
+                .iter()
+                .chain(self.deferred.values().flatten())
+                .chain(self.in_flight.values())
+                .cloned()
+                .collect(),
+            pages: self.pages,
         }
     }
 
+    pub fn root(&self) -> &Url {
+        &self.link_map.root
+    }
+
     pub fn get_result(&self) -> LinkMap {
         self.link_map.clone()
     }
 
-    pub fn push_processor(&mut self, worker_res: WorkerResult) {
-        self.processors.push_front(worker_res);
+    pub fn push_processor(
+        &mut self,
+        process: Process,
+        worker: impl Future<Output = WorkerResult> + Send + 'static,
+    ) {
+        let id = self.processors.spawn(worker).id();
+        self.tasks.insert(id, (process.url.clone(), self.session));
+        self.in_flight.insert(process.url.clone(), process);
+    }
+
+    // forgets the worker's process, letting the next process deferred for its host start, and
+    // returns the session its request was sent in
+    fn finish(&mut self, id: task::Id) -> u64 {
+        match self.tasks.remove(&id) {
+            Some((url, session)) => {
+                self.in_flight.remove(&url);
+                self.undefer(&url);
+                session
+            }
+            None => self.session,
+        }
+    }
+
+    /// Waits for the next worker to finish, returning its result along with the login session
+    /// its request was sent in, or `None` once no workers are running.
+    pub async fn get_next_result(&mut self) -> Option<(WorkerResult, u64)> {
+        while let Some(result) = self.processors.join_next_with_id().await {
+            match result {
+                Ok((id, result)) => {
+                    let session = self.finish(id);
+                    return Some((result, session));
+                }
+                Err(err) => {
+                    tracing::error!("Worker failed - {}", err);
+                    self.finish(err.id());
+                }
+            }
+        }
+        None
     }
 
-    pub fn get_next_processor(&mut self) -> Option<WorkerResult> {
-        self.processors.pop_front()
+    pub fn has_running(&self) -> bool {
+        !self.processors.is_empty()
     }
 
+    /// The next process whose scheduled time has come.
     pub fn get_next_process(&mut self) -> Option<Process> {
-        self.heap.pop()
+        match self.heap.peek() {
+            Some(process) if process.timestamp <= Timestamp::now() => self.heap.pop(),
+            _ => None,
+        }
+    }
+
+    /// When the next process is scheduled, if any are queued.
+    pub fn next_scheduled(&self) -> Option<Timestamp> {
+        self.heap.peek().map(|process| process.timestamp)
+    }
+
+    /// Queues a process to start once its host allows, after `wait`.
+    pub fn reschedule(&mut self, process: Process, wait: std::time::Duration) {
+        self.heap.push(Process {
+            timestamp: Timestamp::now().checked_add(wait).unwrap_or(Timestamp::MAX),
+            ..process
+        });
     }
 
-    pub fn queue_to_process(&mut self, url: &str, retry: u8, initial_retry_delay_ms: &u16) {
+    pub fn defer(&mut self, process: Process) {
+        self.deferred
+            .entry(host_key(&process.url))
+            .or_default()
+            .push_back(process);
+    }
+
+    /// Requeues every deferred process, returning whether there were any.
+    pub fn undefer_all(&mut self) -> bool {
+        let deferred: Vec<Process> = self.deferred.drain().flat_map(|(_, d)| d).collect();
+        let any = !deferred.is_empty();
+        for process in deferred {
+            self.heap.push(process);
+        }
+        any
+    }
+
+    /// Requeues the next process deferred for this URL's host.
+    fn undefer(&mut self, url: &Url) {
+        if let Some(process) = self
+            .deferred
+            .get_mut(&host_key(url))
+            .and_then(|deferred| deferred.pop_front())
+        {
+            self.heap.push(process);
+        }
+    }
+
+    pub fn queue_to_process(
+        &mut self,
+        url: &Url,
+        retry: u8,
+        depth: u16,
+        initial_retry_delay_ms: &u16,
+    ) {
         if retry == 0 {
             if self.seen.contains(url) {
-                return;
+                // a URL skipped for being too deep or nofollow may since have been found closer
+                // to the root or by a followed link
+                if !self.is_skipped_provisionally(url) {
+                    return;
+                }
+                self.link_map.map.remove(url);
             }
-            self.seen.insert(url.to_string());
+            self.seen.insert(url.clone());
+            self.pages += 1;
         }
         self.heap
-            .push(Process::new(&url, retry, initial_retry_delay_ms));
+            .push(Process::new(url, retry, depth, initial_retry_delay_ms));
+    }
+
+    /// Queues a link outside the site or a resource to be checked, which doesn't count towards
+    /// the pages visited.
+    pub fn queue_to_check(&mut self, url: &Url, depth: u16) {
+        if self.seen.insert(url.clone()) {
+            self.heap.push(Process {
+                check_only: true,
+                ..Process::new(url, 0, depth, &0)
+            });
+        }
+    }
+
+    // skipped for a reason which may not apply if the URL is found again
+    fn is_skipped_provisionally(&self, url: &Url) -> bool {
+        matches!(
+            self.link_map.map.get(url),
+            Some(LinkMapValue::Skipped(
+                SkipReason::Limit | SkipReason::Nofollow
+            ))
+        )
+    }
+
+    /// Whether the URL is yet to be queued, including when it was previously skipped for a limit
+    /// or nofollow.
+    pub fn is_unvisited(&self, url: &Url) -> bool {
+        !self.seen.contains(url) || self.is_skipped_provisionally(url)
+    }
+
+    pub fn skip(&mut self, url: &Url, reason: SkipReason) {
+        if self.link_map.map.contains_key(url) && !self.is_skipped_provisionally(url) {
+            return;
+        }
+        self.seen.insert(url.clone());
+        self.add_result(url, LinkMapValue::Skipped(reason));
+    }
+
+    /// Marks a URL fetched along the way to another, such as a redirect followed by the client.
+    pub fn mark_seen(&mut self, url: &Url) {
+        self.seen.insert(url.clone());
+    }
+
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// The login session requests are currently sent in.
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Starts a new login session, recording whether logging in succeeded.
+    pub fn renew_session(&mut self, logged_in: bool) {
+        self.session += 1;
+        self.logged_out = !logged_in;
+    }
+
+    /// Whether the latest login failed, so requests aren't worth retrying for an expired session.
+    pub fn is_logged_out(&self) -> bool {
+        self.logged_out
+    }
+
+    pub fn add_result(&mut self, url: &Url, result: LinkMapValue) {
+        self.link_map.add(url.clone(), result);
     }
 
-    pub fn add_result(&mut self, url: &str, result: LinkMapValue) {
-        self.link_map.add(url.to_string(), result);
+    pub fn add_sitemap_url(&mut self, url: &Url) {
+        self.link_map.sitemap.insert(url.clone());
     }
 
     pub fn has_process_capacity(&self) -> bool {
-        self.processors.len() < self.processors.capacity()
+        self.processors.len() < self.worker_pool_size
     }
 
     fn progress_bar(&self) -> String {
@@ -74,7 +301,7 @@ impl Trace {
             " | {}/{} ... {} queued, {} in processing",
             completed,
             total,
-            &self.heap.len(),
+            self.heap.len() + self.deferred.values().map(|d| d.len()).sum::<usize>(),
             &self.processors.len()
         );
         bar
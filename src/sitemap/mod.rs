@@ -0,0 +1,132 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use thiserror::Error;
+use url::Url;
+
+/// Largest uncompressed sitemap read, guarding against gzip bombs. The protocol allows 50MB.
+const MAX_SIZE: u64 = 64 * 1024 * 1024;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SitemapError {
+    #[error("unable to read sitemap content")]
+    Content(String),
+    #[error("invalid sitemap xml")]
+    Xml(String),
+    #[error("not a sitemap")]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sitemap {
+    /// A `sitemapindex` listing further sitemaps
+    Index(Vec<Url>),
+    /// A `urlset` listing pages
+    UrlSet(Vec<Url>),
+}
+
+fn decode(content: &[u8]) -> Result<String, SitemapError> {
+    let mut text = String::new();
+    let read = match content.starts_with(&GZIP_MAGIC) {
+        true => GzDecoder::new(content)
+            .take(MAX_SIZE)
+            .read_to_string(&mut text),
+        false => content.take(MAX_SIZE).read_to_string(&mut text),
+    };
+    read.map_err(|err| SitemapError::Content(err.to_string()))?;
+    Ok(text)
+}
+
+/// Parses a sitemap index or urlset, decompressing it first if gzipped.
+pub fn parse(content: &[u8]) -> Result<Sitemap, SitemapError> {
+    let text = decode(content)?;
+    let doc =
+        roxmltree::Document::parse(&text).map_err(|err| SitemapError::Xml(err.to_string()))?;
+    let locs = |entry: &str| -> Vec<Url> {
+        doc.root_element()
+            .children()
+            .filter(|node| node.has_tag_name(entry))
+            .flat_map(|node| node.children().filter(|child| child.has_tag_name("loc")))
+            .filter_map(|loc| loc.text())
+            .filter_map(|loc| match Url::parse(loc.trim()) {
+                Ok(url) => Some(url),
+                Err(err) => {
+                    tracing::debug!("Ignoring sitemap loc {:?} - {}", loc, err);
+                    None
+                }
+            })
+            .collect()
+    };
+    match doc.root_element().tag_name().name() {
+        "sitemapindex" => Ok(Sitemap::Index(locs("sitemap"))),
+        "urlset" => Ok(Sitemap::UrlSet(locs("url"))),
+        other => Err(SitemapError::Unknown(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use crate::test_support::to_url;
+
+    use super::*;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc> http://example.com/one </loc>
+    <lastmod>2024-05-01</lastmod>
+  </url>
+  <url><loc>http://example.com/two?a=1&amp;b=2</loc></url>
+  <url><loc>not a url</loc></url>
+</urlset>"#;
+
+    #[test]
+    fn parses_urlset() {
+        assert_eq!(
+            parse(URLSET.as_bytes()),
+            Ok(Sitemap::UrlSet(vec![
+                to_url("http://example.com/one"),
+                to_url("http://example.com/two?a=1&b=2"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn parses_sitemap_index() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>http://example.com/sitemap-1.xml</loc></sitemap>
+  <sitemap><loc>http://example.com/sitemap-2.xml.gz</loc></sitemap>
+</sitemapindex>"#;
+        assert_eq!(
+            parse(content.as_bytes()),
+            Ok(Sitemap::Index(vec![
+                to_url("http://example.com/sitemap-1.xml"),
+                to_url("http://example.com/sitemap-2.xml.gz"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn parses_gzipped_sitemap() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(URLSET.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(parse(&gzipped), parse(URLSET.as_bytes()));
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert_eq!(
+            parse(b"<html><body>not found</body></html>"),
+            Err(SitemapError::Unknown("html".to_string()))
+        );
+        assert!(matches!(parse(b"User-agent: *"), Err(SitemapError::Xml(_))));
+    }
+}
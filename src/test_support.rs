@@ -0,0 +1,6 @@
+//! Helpers shared by the unit tests.
+use url::Url;
+
+pub fn to_url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+}